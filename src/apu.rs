@@ -1,3 +1,4 @@
+use proc_bitfield::bitfield;
 use rodio::{OutputStream, Sink, buffer::SamplesBuffer};
 
 const AUDIO_DEBUG: bool = false;
@@ -17,6 +18,102 @@ fn dac(digital: u8) -> f32 {
     (((digital as f32) / 15.0) * 2.0) - 1.0
 }
 
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr10(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub pace: u8 @ 4..=6,
+        pub decrease: bool @ 3,
+        pub step: u8 @ 0..=2,
+    }
+}
+
+bitfield! {
+    // NR11, NR21 and NR41 (which only has the length)
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct LengthDuty(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub duty: u8 @ 6..=7,
+        pub initial_length: u8 @ 0..=5,
+    }
+}
+
+bitfield! {
+    // NR12, NR22 and NR42
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Envelope(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub initial_volume: u8 @ 4..=7,
+        pub increase: bool @ 3,
+        pub pace: u8 @ 0..=2,
+    }
+}
+
+bitfield! {
+    // NR14, NR24, NR34 and NR44 (which has no period bits)
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Control(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub trigger: bool @ 7,
+        pub length_enable: bool @ 6,
+        pub period_high: u8 @ 0..=2,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr30(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub dac_enable: bool @ 7,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr32(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub output_level: u8 @ 5..=6,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr43(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub clock_shift: u8 @ 4..=7,
+        pub short_lfsr: bool @ 3,
+        pub clock_divider: u8 @ 0..=2,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr50(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub vin_left: bool @ 7,
+        pub left_volume: u8 @ 4..=6,
+        pub vin_right: bool @ 3,
+        pub right_volume: u8 @ 0..=2,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr51(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub ch4_left: bool @ 7,
+        pub ch3_left: bool @ 6,
+        pub ch2_left: bool @ 5,
+        pub ch1_left: bool @ 4,
+        pub ch4_right: bool @ 3,
+        pub ch3_right: bool @ 2,
+        pub ch2_right: bool @ 1,
+        pub ch1_right: bool @ 0,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Nr52(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub audio_on: bool @ 7,
+        pub ch4_on: bool @ 3,
+        pub ch3_on: bool @ 2,
+        pub ch2_on: bool @ 1,
+        pub ch1_on: bool @ 0,
+    }
+}
+
 pub struct Apu {
     ch1: Channel1,
     ch2: Channel2,
@@ -28,8 +125,8 @@ pub struct Apu {
     nr52: u8, // sound on/off
     wave_ram: [u8; 0x10],
 
-    sink: Sink,
-    _stream: OutputStream, // stream needs to be kept alive
+    sink: Option<Sink>, // no sink when there's no audio device, e.g. in the debugger repl
+    _stream: Option<OutputStream>, // stream needs to be kept alive
     cycles: u64,
 
     sample_buffer: Vec<f32>,
@@ -41,8 +138,24 @@ pub struct Apu {
 
 impl Apu {
     pub fn new() -> Self {
-        let stream = rodio::OutputStreamBuilder::open_default_stream().unwrap();
-        let sink = Sink::connect_new(stream.mixer());
+        let stream = match rodio::OutputStreamBuilder::open_default_stream() {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                println!("WARNING: couldn't open audio output, running without sound: {e}");
+                None
+            }
+        };
+        Self::with_stream(stream)
+    }
+
+    // runs exactly the same, just never opens an output. for player 2 so only one of the two
+    // gameboys is heard, and for tests
+    pub fn muted() -> Self {
+        Self::with_stream(None)
+    }
+
+    fn with_stream(stream: Option<OutputStream>) -> Self {
+        let sink = stream.as_ref().map(|s| Sink::connect_new(s.mixer()));
 
         Self {
             ch1: Channel1::default(),
@@ -147,11 +260,13 @@ impl Apu {
                 self.sample_buffer.push(sample);
             }
             if self.sample_buffer.len() >= SAMPLE_BUF_SIZE && !AUDIO_DEBUG {
-                self.sink.append(SamplesBuffer::new(
-                    2,
-                    AUDIO_SAMPLE_RATE,
-                    self.sample_buffer.clone(),
-                ));
+                if let Some(sink) = &self.sink {
+                    sink.append(SamplesBuffer::new(
+                        2,
+                        AUDIO_SAMPLE_RATE,
+                        self.sample_buffer.clone(),
+                    ));
+                }
                 self.sample_buffer.clear();
             }
         }
@@ -189,6 +304,7 @@ struct Channel1 {
 
     cycles: u8,
     duty_step: usize,
+    #[allow(dead_code)] // not hooked up yet
     length_timer: u16,
     div_cache: u8,
     period_div: u16,
@@ -204,7 +320,7 @@ impl Channel1 {
         // div has changed, update stuff
         if div_apu != self.div_cache {
             self.div_cache = div_apu;
-            if div_apu % 2 == 0 {
+            if div_apu.is_multiple_of(2) {
                 // length timer
             }
         }
@@ -11,29 +11,55 @@ pub struct Bus {
     pub timer: Timer,
     pub ppu: Ppu,
     pub joypad: Joypad,
-    serial: Serial,
+    pub serial: Serial,
+    pub cgb: bool,          // running in CGB mode, only for CGB only carts
+    pub double_speed: bool, // CGB only, set by STOP once KEY1 is armed
+    speed_switch: bool,     // KEY1 bit 0, the next STOP switches speed
     apu: Apu,
+    dma: Dma,
+}
+
+// OAM DMA copies one byte per M-cycle, and while it's running the CPU only has HRAM
+#[derive(Debug, Default)]
+struct Dma {
+    reg: u8, // last value written to 0xFF46
+    active: bool,
+    starting: bool, // a write to 0xFF46 takes an M-cycle before the first byte moves
+    src: u16,
+    index: u16,
+    cycles: u64,
 }
 
 impl Bus {
-    pub fn new(cart: Box<dyn Mbc>) -> Self {
+    pub fn new(cart: Box<dyn Mbc>, apu: Apu) -> Self {
+        // 0x80 is "works on both", 0xC0 is CGB only. there's no CGB graphics here, so carts
+        // that also run on a DMG get treated like they're on one
+        let cgb = cart.rom()[0x0143] == 0xC0;
+        let mut serial = Serial::new();
+        serial.cgb = cgb;
         Bus {
             ram: [0; 0x10000],
             cart,
             timer: Timer::new(),
             ppu: Ppu::new(),
             joypad: Joypad::new(),
-            serial: Serial::new(),
-            apu: Apu::new(),
+            serial,
+            cgb,
+            double_speed: false,
+            speed_switch: false,
+            apu,
+            dma: Dma::default(),
         }
     }
 
     pub fn tick(&mut self, cycles: u64) {
+        self.tick_dma(cycles);
+
         self.joypad.tick();
         self.ram[0xFF0F] |= (self.joypad.joypad_int as u8) << 4;
         self.joypad.joypad_int = false;
 
-        self.serial.tick(cycles);
+        self.serial.tick(cycles, self.timer.system_counter());
         self.ram[0xFF0F] |= (self.serial.serial_int as u8) << 3;
         self.serial.serial_int = false;
 
@@ -41,13 +67,18 @@ impl Bus {
         self.ram[0xFF0F] |= (self.timer.timer_int as u8) << 2;
         self.timer.timer_int = false;
 
+        // in double speed the cpu, timer and serial port go twice as fast but the ppu and apu
+        // don't, so they only see half the cycles
+        let cycles = cycles >> self.double_speed as u32;
         self.ppu.tick(cycles);
         self.ram[0xFF0F] |= (self.ppu.stat_int as u8) << 1;
         self.ppu.stat_int = false;
         self.ram[0xFF0F] |= self.ppu.vblank_int as u8;
         self.ppu.vblank_int = false;
 
-        self.apu.tick(cycles, self.timer.div);
+        // same goes for the bit of DIV the apu's frame sequencer watches
+        self.apu
+            .tick(cycles, self.timer.div() >> self.double_speed as u32);
     }
 
     fn ram_read(&self, addr: u16) -> u8 {
@@ -59,6 +90,13 @@ impl Bus {
     }
 
     pub fn read_u8(&self, addr: u16) -> u8 {
+        if self.dma_blocked(addr) {
+            return 0xFF;
+        }
+        self.read_u8_direct(addr)
+    }
+
+    fn read_u8_direct(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x3FFF => self.cart.read_u8(addr), // cart rom bank 0
             0x4000..=0x7FFF => self.cart.read_u8(addr), // cart rom bank 01-NN
@@ -78,7 +116,43 @@ impl Bus {
         }
     }
 
+    // what the debug tools see: no dma or ppu blocking and no warnings
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.peek(addr),
+            0xFEA0..=0xFEFF => self.ram_read(addr),
+            _ => self.read_u8_direct(addr),
+        }
+    }
+
+    // same for writes, except rom can't be poked since writes there go to the mbc registers.
+    // returns whether the write happened
+    pub fn poke(&mut self, addr: u16, val: u8) -> bool {
+        match addr {
+            0x0000..=0x7FFF => return false,
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.poke(addr, val),
+            0xFEA0..=0xFEFF => self.ram_write(addr, val),
+            _ => self.write_u8_direct(addr, val),
+        }
+        true
+    }
+
+    // which rom bank addr reads from, everything outside the cart rom counts as bank 0
+    pub fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x7FFF => self.cart.rom_bank(addr),
+            _ => 0,
+        }
+    }
+
     pub fn write_u8(&mut self, addr: u16, val: u8) {
+        if self.dma_blocked(addr) {
+            return;
+        }
+        self.write_u8_direct(addr, val);
+    }
+
+    fn write_u8_direct(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x3FFF => self.cart.write_u8(addr, val), // cart rom bank 0
             0x4000..=0x7FFF => self.cart.write_u8(addr, val), // cart rom bank 01-NN
@@ -98,15 +172,6 @@ impl Bus {
         }
     }
 
-    pub fn read_u16(&self, addr: u16) -> u16 {
-        (self.read_u8(addr) as u16) | ((self.read_u8(addr + 1) as u16) << 8)
-    }
-
-    pub fn write_u16(&mut self, addr: u16, val: u16) {
-        self.write_u8(addr, (val & 0x00FF) as u8);
-        self.write_u8(addr + 1, ((val & 0xFF00) >> 8) as u8);
-    }
-
     fn io_read_u8(&self, addr: u16) -> u8 {
         match addr {
             0xFF00 => self.joypad.read_u8(),              // joypad
@@ -116,7 +181,8 @@ impl Bus {
             0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 => self.apu.read_u8(addr), // audio registers
             0xFF30..=0xFF3F => self.apu.read_u8(addr), // wave ram
             0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.read_u8(addr), // ppu registers
-            0xFF46 => 0xFF, // what does reading from the dma register do?
+            0xFF46 => self.dma.reg,
+            0xFF4D if self.cgb => ((self.double_speed as u8) << 7) | 0x7E | self.speed_switch as u8, // KEY1
             // 0xFF4F => unimplemented!("tried to read CGB VRAM bank select"),
             // 0xFF50 => unimplemented!("tried to read boot rom flag"),
             // 0xFF51..=0xFF55 => unimplemented!("tried to read CGB VRAM DMA"),
@@ -136,6 +202,7 @@ impl Bus {
             0xFF30..=0xFF3F => self.apu.write_u8(addr, val), // wave ram
             0xFF40..0xFF46 | 0xFF47..=0xFF4B => self.ppu.write_u8(addr, val), // ppu registers
             0xFF46 => self.do_dma(val), // dma lives in the bus to make things easier
+            0xFF4D if self.cgb => self.speed_switch = val & 1 != 0, // KEY1
             // 0xFF4F => unimplemented!("tried to write to CGB VRAM bank select"),
             // 0xFF50 => unimplemented!("tried to write to boot rom flag"),
             // 0xFF51..=0xFF55 => unimplemented!("tried to write to CGB VRAM DMA"),
@@ -145,11 +212,85 @@ impl Bus {
         }
     }
 
-    // not that accurate but i'm just gonna trust roms to play nice
+    // whether the next STOP should switch speed instead of stopping
+    pub fn speed_switch_armed(&self) -> bool {
+        self.cgb && self.speed_switch
+    }
+
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.speed_switch = false;
+        self.timer.reset_divider();
+    }
+
     fn do_dma(&mut self, src: u8) {
-        let addr = (src as u16) << 8;
-        for i in 0..=0x9F {
-            self.write_u8(0xFE00 + i, self.read_u8(addr + i));
+        // starting again in the middle of a transfer just restarts it
+        self.dma.reg = src;
+        self.dma.starting = true;
+        self.dma.cycles = 0;
+    }
+
+    // while dma runs the cpu can only reach HRAM. the io registers and IE are inside the
+    // cpu too so they keep working, otherwise interrupts couldn't be checked
+    fn dma_blocked(&self, addr: u16) -> bool {
+        self.dma.active && addr < 0xFF00
+    }
+
+    fn tick_dma(&mut self, cycles: u64) {
+        if !self.dma.active && !self.dma.starting {
+            return;
         }
+        self.dma.cycles += cycles;
+        while self.dma.cycles >= 4 {
+            self.dma.cycles -= 4;
+            if self.dma.starting {
+                self.dma.starting = false;
+                self.dma.active = true;
+                // anything past 0xDFFF reads from WRAM
+                self.dma.src = (self.dma.reg as u16) << 8;
+                if self.dma.src >= 0xE000 {
+                    self.dma.src -= 0x2000;
+                }
+                self.dma.index = 0;
+                continue;
+            }
+
+            let byte = self.read_u8_direct(self.dma.src + self.dma.index);
+            self.ppu.dma_write(self.dma.index as usize, byte);
+            self.dma.index += 1;
+            if self.dma.index >= 0xA0 {
+                self.dma.active = false;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameboy::test_gameboy;
+
+    #[test]
+    fn dma_only_leaves_hram() {
+        let mut gb = test_gameboy(0x00, &[]);
+        let bus = &mut gb.cpu.bus;
+        bus.ppu.access_blocking = false;
+        for i in 0..0xA0 {
+            bus.write_u8(0xC000 + i, i as u8);
+        }
+        bus.write_u8(0xFF80, 0x42);
+        bus.write_u8(0xFF46, 0xC0);
+        bus.tick(8);
+
+        assert_eq!(bus.read_u8(0xC000), 0xFF);
+        assert_eq!(bus.read_u8(0x0100), 0xFF);
+        bus.write_u8(0xC000, 0x99); // dropped
+        assert_eq!(bus.read_u8(0xFF80), 0x42);
+        assert_eq!(bus.read_u8(0xFF46), 0xC0);
+
+        bus.tick(160 * 4);
+        assert_eq!(bus.read_u8(0xC000), 0x00);
+        assert_eq!(bus.read_u8(0xFE01), 0x01);
+        assert_eq!(bus.read_u8(0xFE9F), 0x9F);
     }
 }
@@ -0,0 +1,180 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboy::GbBtn;
+
+// keys are stored by their egui names ("X", "Up", "Space", ...) so the file stays readable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keys {
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    pub a: Vec<String>,
+    pub b: Vec<String>,
+    pub select: Vec<String>,
+    pub start: Vec<String>,
+}
+
+fn keys(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            up: keys(&["Up"]),
+            down: keys(&["Down"]),
+            left: keys(&["Left"]),
+            right: keys(&["Right"]),
+            a: keys(&["X"]),
+            b: keys(&["Z"]),
+            select: keys(&["A"]),
+            start: keys(&["S"]),
+        }
+    }
+}
+
+impl Keys {
+    fn player2() -> Self {
+        Self {
+            up: keys(&["I"]),
+            down: keys(&["K"]),
+            left: keys(&["J"]),
+            right: keys(&["L"]),
+            a: keys(&["M"]),
+            b: keys(&["N"]),
+            select: keys(&["U"]),
+            start: keys(&["O"]),
+        }
+    }
+
+    pub fn get(&self, button: GbBtn) -> &Vec<String> {
+        match button {
+            GbBtn::Up => &self.up,
+            GbBtn::Down => &self.down,
+            GbBtn::Left => &self.left,
+            GbBtn::Right => &self.right,
+            GbBtn::A => &self.a,
+            GbBtn::B => &self.b,
+            GbBtn::Select => &self.select,
+            GbBtn::Start => &self.start,
+        }
+    }
+
+    pub fn get_mut(&mut self, button: GbBtn) -> &mut Vec<String> {
+        match button {
+            GbBtn::Up => &mut self.up,
+            GbBtn::Down => &mut self.down,
+            GbBtn::Left => &mut self.left,
+            GbBtn::Right => &mut self.right,
+            GbBtn::A => &mut self.a,
+            GbBtn::B => &mut self.b,
+            GbBtn::Select => &mut self.select,
+            GbBtn::Start => &mut self.start,
+        }
+    }
+}
+
+// rates are presses per second
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Turbo {
+    pub a: Vec<String>,
+    pub b: Vec<String>,
+    pub a_rate: u32,
+    pub b_rate: u32,
+}
+
+impl Default for Turbo {
+    fn default() -> Self {
+        Self {
+            a: keys(&["V"]),
+            b: keys(&["C"]),
+            a_rate: 10,
+            b_rate: 10,
+        }
+    }
+}
+
+// a key that plays back a few frames of buttons, like
+// [[macros]]
+// name = "soft reset"
+// keys = ["R"]
+// steps = [{ buttons = ["A", "B", "Select", "Start"], frames = 10 }]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    pub keys: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Step {
+    pub buttons: Vec<GbBtn>,
+    pub frames: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub keys: Keys,
+    pub player2_keys: Keys,
+    pub turbo: Turbo,
+    pub macros: Vec<Macro>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keys: Keys::default(),
+            player2_keys: Keys::player2(),
+            turbo: Turbo::default(),
+            macros: vec![Macro {
+                name: "soft reset".to_string(),
+                keys: keys(&["R"]),
+                steps: vec![Step {
+                    buttons: vec![GbBtn::A, GbBtn::B, GbBtn::Select, GbBtn::Start],
+                    frames: 10,
+                }],
+            }],
+        }
+    }
+}
+
+impl Config {
+    // ~/.config/gameboy/config.toml on linux
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("gameboy").join("config.toml"))
+    }
+
+    // a missing file is just the defaults, a broken one gets complained about first
+    pub fn load(path: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match toml::from_str(&text) {
+            Ok(config) => config,
+            Err(e) => {
+                println!(
+                    "WARNING: couldn't read {}, using defaults: {e}",
+                    path.display()
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
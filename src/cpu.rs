@@ -1,5 +1,11 @@
+use crate::apu::Apu;
 use crate::bus::Bus;
+use crate::debugger::MemAccess;
 use crate::mbc::Mbc;
+use crate::trace::Tracer;
+
+// how long the clock stays stopped for a CGB speed switch, in M-cycles
+const SPEED_SWITCH_CYCLES: u16 = 2050;
 
 // instruction timings in T-cycles
 #[rustfmt::skip]
@@ -72,7 +78,20 @@ pub struct Cpu {
     pub bus: Bus,
     ime: bool,
     pending_ime: bool,
-    halted: bool,
+    state: CpuState,
+    halt_bug: bool,
+    cycles: u64,                            // T-cycles spent so far in the current tick
+    pub access_log: Option<Vec<MemAccess>>, // filled in with every access when Some, for watchpoints
+    pub trace: Option<Tracer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    Running,
+    Halted,
+    Stopped,        // everything but the joypad is asleep until a selected line goes low
+    Switching(u16), // CGB speed switch, the clock's stopped for this many more M-cycles
+    Locked(u16),    // hit an illegal opcode at this address, only a reset gets out of this
 }
 
 #[derive(Debug, Default)]
@@ -162,97 +181,230 @@ impl RegisterFile {
 }
 
 impl Cpu {
-    pub fn new(cart: Box<dyn Mbc>) -> Self {
+    pub fn new(cart: Box<dyn Mbc>, apu: Apu) -> Self {
         Self {
             rf: RegisterFile::default(),
-            bus: Bus::new(cart),
+            bus: Bus::new(cart, apu),
             ime: false,
             pending_ime: false,
-            halted: false,
+            state: CpuState::Running,
+            halt_bug: false,
+            cycles: 0,
+            access_log: None,
+            trace: None,
         }
     }
 
     pub fn simulate_boot(&mut self) {
-        self.rf.a = 0x01;
-        self.rf.write_z(true);
-        self.rf.b = 0x00;
-        self.rf.c = 0x13;
-        self.rf.d = 0x00;
-        self.rf.e = 0xD8;
-        self.rf.h = 0x01;
-        self.rf.l = 0x4D;
+        if self.bus.cgb {
+            // A=0x11 is how games tell they're on a CGB
+            self.rf.a = 0x11;
+            self.rf.f = 0x80;
+            self.rf.b = 0x00;
+            self.rf.c = 0x00;
+            self.rf.d = 0xFF;
+            self.rf.e = 0x56;
+            self.rf.h = 0x00;
+            self.rf.l = 0x0D;
+        } else {
+            self.rf.a = 0x01;
+            self.rf.write_z(true);
+            // the boot rom leaves H and C set unless the header checksum happens to be 0
+            let checksum = self.bus.read_u8(0x014D) != 0;
+            self.rf.write_h(checksum);
+            self.rf.write_c(checksum);
+            self.rf.b = 0x00;
+            self.rf.c = 0x13;
+            self.rf.d = 0x00;
+            self.rf.e = 0xD8;
+            self.rf.h = 0x01;
+            self.rf.l = 0x4D;
+        }
         self.rf.pc = 0x0100;
         self.rf.sp = 0xFFFE;
 
-        self.bus.timer.div = 0xAB;
+        // DIV reads 0xAB, the low half is where the boot rom leaves it
+        self.bus.timer.counter = 0xABCC;
         self.bus.timer.tima = 0x00;
         self.bus.timer.tma = 0x00;
-        self.bus.timer.tac = 0xF8;
+        self.bus.timer.tac = 0xF8.into();
+    }
+
+    pub fn state(&self) -> CpuState {
+        self.state
+    }
+
+    pub fn registers(&self) -> &RegisterFile {
+        &self.rf
+    }
+
+    pub fn registers_mut(&mut self) -> &mut RegisterFile {
+        &mut self.rf
     }
 
+    // the bus gets ticked along with every memory access, so this only returns how long it took
     pub fn tick(&mut self) -> u64 {
-        let icycles = self.handle_interrupts();
-        if self.pending_ime {
+        self.cycles = 0;
+        if let CpuState::Locked(_) = self.state {
+            // the rest of the system keeps going without us
+            self.idle();
+            return self.cycles;
+        }
+        if self.state == CpuState::Stopped {
+            // the clock is stopped so nothing else gets ticked, time still passes for the frontend
+            self.bus.joypad.tick();
+            if self.bus.joypad.lines_low() {
+                self.bus.ppu.resume();
+                self.state = CpuState::Running;
+            }
+            self.cycles += 4;
+            return self.cycles;
+        }
+        if let CpuState::Switching(left) = self.state {
+            self.state = match left {
+                0 | 1 => CpuState::Running,
+                _ => CpuState::Switching(left - 1),
+            };
+            self.cycles += 4;
+            return self.cycles;
+        }
+        // EI only kicks in once the instruction after it is done
+        let enable_ime = self.pending_ime;
+        // dispatching counts as its own step so the debugger can stop on the first instruction
+        // of the handler
+        if self.handle_interrupts() {
+            return self.cycles;
+        }
+
+        let start = self.cycles;
+        let expected = self.execute_instruction();
+        // the timing tables are the reference for how many cycles each instruction should take
+        debug_assert_eq!(
+            self.cycles - start,
+            expected,
+            "cycle count mismatch before pc {:#06X}",
+            self.rf.pc
+        );
+
+        if enable_ime && self.pending_ime {
             self.ime = true;
             self.pending_ime = false;
         }
-        let cycles = icycles + self.execute_instruction();
-        self.bus.tick(cycles);
-        cycles
+        self.cycles
     }
 
-    fn handle_interrupts(&mut self) -> u64 {
-        if self.pending_ime {
-            self.ime = true;
-            self.pending_ime = false;
+    // one M-cycle that doesn't touch memory
+    fn idle(&mut self) {
+        self.bus.tick(4);
+        self.cycles += 4;
+    }
+
+    fn read_u8(&mut self, addr: u16) -> u8 {
+        self.idle();
+        let val = self.bus.read_u8(addr);
+        if let Some(log) = &mut self.access_log {
+            log.push(MemAccess {
+                addr,
+                val,
+                write: false,
+            });
         }
+        val
+    }
 
-        let i_flags = self.bus.read_u8(0xFF0F);
-        let i_enable = self.bus.read_u8(0xFFFF);
-
-        if i_flags & i_enable != 0 {
-            if self.ime {
-                let int = (i_flags & i_enable).trailing_zeros(); // is this a clever way to do this? i hope so! (it wasn't i had to fix it)
-                let addr = match int {
-                    0 => 0x0040, // VBlank
-                    1 => 0x0048, // LCD
-                    2 => 0x0050, // Timer
-                    3 => 0x0058, // Serial
-                    4 => 0x0060, // Joypad
-                    _ => panic!("invalid interrupt??: {int}"),
-                };
-
-                self.ime = false;
-                self.bus.write_u8(0xFF0F, i_flags & !(1 << int)); // update IF
-                self.push_u16(self.rf.pc);
-                self.rf.pc = addr;
-            }
+    fn write_u8(&mut self, addr: u16, val: u8) {
+        self.idle();
+        self.bus.write_u8(addr, val);
+        if let Some(log) = &mut self.access_log {
+            log.push(MemAccess {
+                addr,
+                val,
+                write: true,
+            });
+        }
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.read_u8(0xFF0F) & self.bus.read_u8(0xFFFF) & 0x1F
+    }
 
-            self.halted = false;
-            return 20;
+    // returns true if an interrupt was dispatched
+    fn handle_interrupts(&mut self) -> bool {
+        if self.pending_interrupts() == 0 {
+            return false;
+        }
+
+        // waking up takes a cycle, with IME off we just carry on after the HALT
+        if self.state == CpuState::Halted {
+            self.state = CpuState::Running;
+            self.idle();
+        }
+        if !self.ime {
+            return false;
         }
 
-        0
+        self.ime = false;
+        // EI right before a bugged HALT returns to the HALT itself
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.rf.pc = self.rf.pc.wrapping_sub(1);
+        }
+        self.idle();
+        self.idle();
+
+        // the interrupt is only picked after the high byte is pushed, so if that push lands on
+        // IE and clears the pending bit the dispatch gets cancelled and jumps to 0x0000 instead
+        let pc = self.rf.pc;
+        self.rf.sp = self.rf.sp.wrapping_sub(1);
+        self.write_u8(self.rf.sp, (pc >> 8) as u8);
+        let pending = self.pending_interrupts();
+        let addr = if pending == 0 {
+            0x0000
+        } else {
+            let int = pending.trailing_zeros(); // is this a clever way to do this? i hope so! (it wasn't i had to fix it)
+            let i_flags = self.bus.read_u8(0xFF0F);
+            self.bus.write_u8(0xFF0F, i_flags & !(1 << int)); // update IF
+            match int {
+                0 => 0x0040, // VBlank
+                1 => 0x0048, // LCD
+                2 => 0x0050, // Timer
+                3 => 0x0058, // Serial
+                4 => 0x0060, // Joypad
+                _ => panic!("invalid interrupt??: {int}"),
+            }
+        };
+        self.rf.sp = self.rf.sp.wrapping_sub(1);
+        self.write_u8(self.rf.sp, pc as u8);
+        self.rf.pc = addr;
+        self.idle();
+        true
     }
 
+    // instruction bytes skip the access log, read watchpoints shouldn't fire on code running
     fn fetch_u8(&mut self) -> u8 {
+        self.idle();
         let res = self.bus.read_u8(self.rf.pc);
-        self.rf.pc = self.rf.pc.wrapping_add(1);
+        // the halt bug makes the cpu read the byte after HALT twice
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.rf.pc = self.rf.pc.wrapping_add(1);
+        }
         res
     }
 
     fn fetch_u16(&mut self) -> u16 {
-        let res = self.bus.read_u16(self.rf.pc);
-        self.rf.pc = self.rf.pc.wrapping_add(2);
-        res
+        let lo = self.fetch_u8() as u16;
+        let hi = self.fetch_u8() as u16;
+        (hi << 8) | lo
     }
 
-    fn read_hl_ind(&self) -> u8 {
-        self.bus.read_u8(self.rf.read_hl())
+    fn read_hl_ind(&mut self) -> u8 {
+        self.read_u8(self.rf.read_hl())
     }
 
     fn write_hl_ind(&mut self, val: u8) {
-        self.bus.write_u8(self.rf.read_hl(), val);
+        self.write_u8(self.rf.read_hl(), val);
     }
 
     fn write_r8(&mut self, reg: u8, val: u8) {
@@ -269,7 +421,7 @@ impl Cpu {
         }
     }
 
-    fn read_r8(&self, reg: u8) -> u8 {
+    fn read_r8(&mut self, reg: u8) -> u8 {
         match reg {
             0 => self.rf.b,
             1 => self.rf.c,
@@ -283,22 +435,31 @@ impl Cpu {
         }
     }
 
+    // high byte goes first
     fn push_u16(&mut self, val: u16) {
-        self.rf.sp = self.rf.sp.wrapping_sub(2);
-        self.bus.write_u16(self.rf.sp, val);
+        self.rf.sp = self.rf.sp.wrapping_sub(1);
+        self.write_u8(self.rf.sp, (val >> 8) as u8);
+        self.rf.sp = self.rf.sp.wrapping_sub(1);
+        self.write_u8(self.rf.sp, val as u8);
     }
 
     fn pop_u16(&mut self) -> u16 {
-        let val = self.bus.read_u16(self.rf.sp);
-        self.rf.sp = self.rf.sp.wrapping_add(2);
-        val
+        let lo = self.read_u8(self.rf.sp) as u16;
+        self.rf.sp = self.rf.sp.wrapping_add(1);
+        let hi = self.read_u8(self.rf.sp) as u16;
+        self.rf.sp = self.rf.sp.wrapping_add(1);
+        (hi << 8) | lo
     }
 
     pub fn execute_instruction(&mut self) -> u64 {
-        if self.halted {
+        if self.state == CpuState::Halted {
+            self.idle();
             return 4;
         }
 
+        if let Some(trace) = &mut self.trace {
+            trace.log(&self.rf, &self.bus);
+        }
         let opcode = self.fetch_u8();
         match opcode {
             //misc
@@ -306,8 +467,8 @@ impl Cpu {
             0xCB => return self.cb_execute_instruction(), // cb prefixed instructions
             0xF3 => self.di(),                            // DI
             0xFB => self.ei(),                            // EI
-            0x10 => self.stop(),                          // STOP
-            0x76 => self.halted = true,                   // HALT
+            0x10 => return self.stop(),                   // STOP
+            0x76 => self.halt(),                          // HALT
             // loads
             0x01 | 0x11 | 0x21 | 0x31 => self.ld_r16_imm(opcode),
             0x06 | 0x16 | 0x26 | 0x36 | 0x0E | 0x1E | 0x2E | 0x3E => self.ld_r8_imm(opcode),
@@ -349,11 +510,11 @@ impl Cpu {
             0xC2 | 0xD2 | 0xC3 | 0xE9 | 0xCA | 0xDA => return self.jp(opcode),
             0xC4 | 0xD4 | 0xCC | 0xDC | 0xCD => return self.call(opcode),
             0xC7 | 0xD7 | 0xE7 | 0xF7 | 0xCF | 0xDF | 0xEF | 0xFF => self.rst(opcode),
-            _ => unimplemented!(
-                "unimplemented opcode {:#04X} at pc {:#06X}",
-                opcode,
-                self.rf.pc - 1,
-            ),
+            // the 11 undefined opcodes hang the cpu on hardware
+            _ => {
+                self.state = CpuState::Locked(self.rf.pc.wrapping_sub(1));
+                return 4;
+            }
         }
 
         INSTRUCTION_TIMINGS[opcode as usize]
@@ -394,8 +555,49 @@ impl Cpu {
         }
     }
 
-    fn stop(&mut self) {
+    fn halt(&mut self) {
+        // with IME off and an interrupt already waiting HALT doesn't halt at all, and PC
+        // doesn't get incremented on the next fetch
+        if !self.ime && self.pending_interrupts() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.state = CpuState::Halted;
+        }
+    }
+
+    // see pandocs "Using the STOP instruction", on CGB an armed KEY1 turns it into a speed
+    // switch instead
+    fn stop(&mut self) -> u64 {
+        self.bus.joypad.tick();
+        let pending = self.pending_interrupts() != 0;
+
+        if self.bus.joypad.lines_low() {
+            // a button is already held, so STOP can't wait for one
+            if pending {
+                return 4;
+            }
+            self.fetch_u8();
+            self.state = CpuState::Halted;
+            return 8;
+        }
+
+        if self.bus.speed_switch_armed() {
+            // with an interrupt pending real hardware does something unpredictable, switching
+            // anyway is as good a guess as any
+            self.fetch_u8();
+            self.bus.switch_speed();
+            self.state = CpuState::Switching(SPEED_SWITCH_CYCLES);
+            return 8;
+        }
+
         self.bus.timer.reset_divider();
+        self.bus.ppu.stop();
+        self.state = CpuState::Stopped;
+        if pending {
+            return 4;
+        }
+        self.fetch_u8();
+        8
     }
 
     // loads
@@ -418,8 +620,8 @@ impl Cpu {
 
     fn ld_ind_a(&mut self, opcode: u8) {
         match opcode {
-            0x02 => self.bus.write_u8(self.rf.read_bc(), self.rf.a),
-            0x12 => self.bus.write_u8(self.rf.read_de(), self.rf.a),
+            0x02 => self.write_u8(self.rf.read_bc(), self.rf.a),
+            0x12 => self.write_u8(self.rf.read_de(), self.rf.a),
             0x22 => {
                 self.write_hl_ind(self.rf.a);
                 self.rf.write_hl(self.rf.read_hl() + 1);
@@ -428,8 +630,8 @@ impl Cpu {
                 self.write_hl_ind(self.rf.a);
                 self.rf.write_hl(self.rf.read_hl() - 1);
             }
-            0x0A => self.rf.a = self.bus.read_u8(self.rf.read_bc()),
-            0x1A => self.rf.a = self.bus.read_u8(self.rf.read_de()),
+            0x0A => self.rf.a = self.read_u8(self.rf.read_bc()),
+            0x1A => self.rf.a = self.read_u8(self.rf.read_de()),
             0x2A => {
                 self.rf.a = self.read_hl_ind();
                 self.rf.write_hl(self.rf.read_hl() + 1);
@@ -445,16 +647,19 @@ impl Cpu {
     fn ld_r8(&mut self, opcode: u8) {
         let src = opcode & 7;
         let dest = (opcode >> 3) & 7;
-        self.write_r8(dest, self.read_r8(src));
+        let val = self.read_r8(src);
+        self.write_r8(dest, val);
     }
 
     fn ld_ind_imm_sp(&mut self) {
         let addr = self.fetch_u16();
-        self.bus.write_u16(addr, self.rf.sp);
+        self.write_u8(addr, self.rf.sp as u8);
+        self.write_u8(addr.wrapping_add(1), (self.rf.sp >> 8) as u8);
     }
 
     fn ld_sp(&mut self) {
         self.rf.sp = self.rf.read_hl();
+        self.idle();
     }
 
     fn pop(&mut self, opcode: u8) {
@@ -469,6 +674,7 @@ impl Cpu {
     }
 
     fn push(&mut self, opcode: u8) {
+        self.idle();
         match opcode {
             0xC5 => self.push_u16(self.rf.read_bc()),
             0xD5 => self.push_u16(self.rf.read_de()),
@@ -488,6 +694,7 @@ impl Cpu {
         self.rf.write_c((a & 0xFF) + (b as u16) > 0xFF);
 
         self.rf.write_hl(a.wrapping_add_signed((b as i8) as i16));
+        self.idle();
     }
 
     fn ld_a_ind_offset(&mut self, opcode: u8) {
@@ -497,10 +704,10 @@ impl Cpu {
             self.rf.c
         } as u16;
         match opcode {
-            0xE0 => self.bus.write_u8(0xFF00 + offset, self.rf.a),
-            0xF0 => self.rf.a = self.bus.read_u8(0xFF00 + offset),
-            0xE2 => self.bus.write_u8(0xFF00 + offset, self.rf.a),
-            0xF2 => self.rf.a = self.bus.read_u8(0xFF00 + offset),
+            0xE0 => self.write_u8(0xFF00 + offset, self.rf.a),
+            0xF0 => self.rf.a = self.read_u8(0xFF00 + offset),
+            0xE2 => self.write_u8(0xFF00 + offset, self.rf.a),
+            0xF2 => self.rf.a = self.read_u8(0xFF00 + offset),
             _ => panic!("invalid opcode {opcode:#04X} in la_a_ind_offset"),
         }
     }
@@ -508,8 +715,8 @@ impl Cpu {
     fn ld_a_ind(&mut self, opcode: u8) {
         let addr = self.fetch_u16();
         match opcode {
-            0xEA => self.bus.write_u8(addr, self.rf.a),
-            0xFA => self.rf.a = self.bus.read_u8(addr),
+            0xEA => self.write_u8(addr, self.rf.a),
+            0xFA => self.rf.a = self.read_u8(addr),
             _ => panic!("invalid opcode {opcode:#04X} in la_a_ind"),
         }
     }
@@ -556,6 +763,7 @@ impl Cpu {
     }
 
     fn inc16(&mut self, opcode: u8) {
+        self.idle();
         match opcode {
             0x03 => self.rf.write_bc(self.rf.read_bc().wrapping_add(1)),
             0x13 => self.rf.write_de(self.rf.read_de().wrapping_add(1)),
@@ -578,6 +786,7 @@ impl Cpu {
     }
 
     fn dec16(&mut self, opcode: u8) {
+        self.idle();
         match opcode {
             0x0B => self.rf.write_bc(self.rf.read_bc().wrapping_sub(1)),
             0x1B => self.rf.write_de(self.rf.read_de().wrapping_sub(1)),
@@ -614,6 +823,7 @@ impl Cpu {
         self.rf.write_h((a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF);
         self.rf.write_c(res < a);
         self.rf.write_hl(res);
+        self.idle();
     }
 
     fn add(&mut self, opcode: u8) {
@@ -801,6 +1011,8 @@ impl Cpu {
         self.rf.write_c((a & 0xFF) + (b as u16) > 0xFF);
 
         self.rf.sp = a.wrapping_add_signed((b as i8) as i16);
+        self.idle();
+        self.idle();
     }
 
     // branches
@@ -816,6 +1028,7 @@ impl Cpu {
         };
         if cond {
             self.rf.pc = self.rf.pc.wrapping_add_signed(offset as i16);
+            self.idle();
             INSTRUCTION_TIMINGS_BRANCH[opcode as usize]
         } else {
             INSTRUCTION_TIMINGS[opcode as usize]
@@ -835,9 +1048,14 @@ impl Cpu {
             }
             _ => panic!("invalid opcode {opcode:#04X} in ret"),
         };
+        // checking the condition takes a cycle
+        if opcode != 0xC9 && opcode != 0xD9 {
+            self.idle();
+        }
 
         if cond {
             self.rf.pc = self.pop_u16();
+            self.idle();
             INSTRUCTION_TIMINGS_BRANCH[opcode as usize]
         } else {
             INSTRUCTION_TIMINGS[opcode as usize]
@@ -863,6 +1081,9 @@ impl Cpu {
 
         if cond {
             self.rf.pc = addr;
+            if opcode != 0xE9 {
+                self.idle();
+            }
             INSTRUCTION_TIMINGS_BRANCH[opcode as usize]
         } else {
             INSTRUCTION_TIMINGS[opcode as usize]
@@ -882,6 +1103,7 @@ impl Cpu {
         };
 
         if cond {
+            self.idle();
             self.push_u16(self.rf.pc);
             self.rf.pc = addr;
             INSTRUCTION_TIMINGS_BRANCH[opcode as usize]
@@ -891,6 +1113,7 @@ impl Cpu {
     }
 
     fn rst(&mut self, opcode: u8) {
+        self.idle();
         self.push_u16(self.rf.pc);
         self.rf.pc = (opcode & 0x38) as u16;
     }
@@ -1022,3 +1245,71 @@ impl Cpu {
         self.write_r8(reg, res);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CpuState;
+    use crate::apu::Apu;
+    use crate::gameboy::{Gameboy, test_gameboy};
+    use crate::mbc::create_cart;
+    use std::path::PathBuf;
+
+    #[test]
+    fn illegal_opcode_locks_up() {
+        let mut gb = test_gameboy(0x00, &[0x00, 0xD3, 0x00]);
+        gb.step_instruction();
+        gb.step_instruction();
+        assert!(matches!(gb.cpu_state(), CpuState::Locked(0x0101)));
+        // and stays there
+        gb.step_instruction();
+        assert!(matches!(gb.cpu_state(), CpuState::Locked(0x0101)));
+    }
+
+    #[test]
+    fn stop_turns_the_lcd_off_until_a_button() {
+        #[rustfmt::skip]
+        let code = [
+            0x3E, 0x10,       // ld a, $10 (select the buttons)
+            0xE0, 0x00,       // ldh ($00), a
+            0xF0, 0x44,       // ldh a, ($44)
+            0xFE, 0x05,       // cp 5
+            0x20, 0xFA,       // jr nz, -6
+            0x10, 0x00,       // stop
+            0x18, 0xFE,       // jr -2
+        ];
+        let mut gb = test_gameboy(0x00, &code);
+        while gb.cpu_state() != CpuState::Stopped {
+            gb.step_instruction();
+        }
+        assert_eq!(gb.cpu.bus.read_u8(0xFF44), 0);
+        assert_eq!(gb.cpu.bus.read_u8(0xFF41) & 3, 0);
+        for _ in 0..1000 {
+            gb.step_instruction();
+        }
+        assert_eq!(gb.cpu_state(), CpuState::Stopped);
+        assert_eq!(gb.cpu.bus.read_u8(0xFF44), 0);
+
+        gb.cpu.bus.joypad.a = true;
+        gb.step_instruction();
+        assert_eq!(gb.cpu_state(), CpuState::Running);
+        assert_eq!(gb.cpu.bus.read_u8(0xFF41) & 3, 2);
+        for _ in 0..1000 {
+            gb.step_instruction();
+        }
+        assert_ne!(gb.cpu.bus.read_u8(0xFF44), 0);
+        // the LCD was never switched off by the game
+        assert_eq!(gb.cpu.bus.read_u8(0xFF40) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn only_cgb_carts_boot_as_cgb() {
+        for (header, a, cgb) in [(0x00, 0x01, false), (0x80, 0x01, false), (0xC0, 0x11, true)] {
+            let mut rom = vec![0; 0x8000];
+            rom[0x0143] = header;
+            let gb = Gameboy::from_cart(create_cart(rom, None), PathBuf::new(), Apu::muted());
+            assert_eq!(gb.cpu.registers().a, a, "header {header:#04X}");
+            assert_eq!(gb.cpu.bus.cgb, cgb, "header {header:#04X}");
+            assert_eq!(gb.cpu.bus.serial.cgb, cgb, "header {header:#04X}");
+        }
+    }
+}
@@ -0,0 +1,638 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::cpu::{Cpu, CpuState, RegisterFile};
+use crate::disasm::{self, Symbols};
+use crate::gameboy::Gameboy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: u16,
+    pub val: u8,
+    pub write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Af,
+    Bc,
+    De,
+    Hl,
+    Sp,
+    Pc,
+}
+
+impl Reg {
+    fn parse(s: &str) -> Option<Self> {
+        let reg = match s.to_ascii_lowercase().as_str() {
+            "a" => Reg::A,
+            "f" => Reg::F,
+            "b" => Reg::B,
+            "c" => Reg::C,
+            "d" => Reg::D,
+            "e" => Reg::E,
+            "h" => Reg::H,
+            "l" => Reg::L,
+            "af" => Reg::Af,
+            "bc" => Reg::Bc,
+            "de" => Reg::De,
+            "hl" => Reg::Hl,
+            "sp" => Reg::Sp,
+            "pc" => Reg::Pc,
+            _ => return None,
+        };
+        Some(reg)
+    }
+
+    pub fn read(self, rf: &RegisterFile) -> u16 {
+        match self {
+            Reg::A => rf.a as u16,
+            Reg::F => rf.f as u16,
+            Reg::B => rf.b as u16,
+            Reg::C => rf.c as u16,
+            Reg::D => rf.d as u16,
+            Reg::E => rf.e as u16,
+            Reg::H => rf.h as u16,
+            Reg::L => rf.l as u16,
+            Reg::Af => rf.read_af(),
+            Reg::Bc => rf.read_bc(),
+            Reg::De => rf.read_de(),
+            Reg::Hl => rf.read_hl(),
+            Reg::Sp => rf.sp,
+            Reg::Pc => rf.pc,
+        }
+    }
+
+    pub fn write(self, rf: &mut RegisterFile, val: u16) {
+        match self {
+            Reg::A => rf.a = val as u8,
+            Reg::F => rf.f = val as u8 & 0xF0,
+            Reg::B => rf.b = val as u8,
+            Reg::C => rf.c = val as u8,
+            Reg::D => rf.d = val as u8,
+            Reg::E => rf.e = val as u8,
+            Reg::H => rf.h = val as u8,
+            Reg::L => rf.l = val as u8,
+            Reg::Af => rf.write_af(val),
+            Reg::Bc => rf.write_bc(val),
+            Reg::De => rf.write_de(val),
+            Reg::Hl => rf.write_hl(val),
+            Reg::Sp => rf.sp = val,
+            Reg::Pc => rf.pc = val,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    fn parse(s: &str) -> Option<Self> {
+        let cmp = match s {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "<" => Cmp::Lt,
+            "<=" => Cmp::Le,
+            ">" => Cmp::Gt,
+            ">=" => Cmp::Ge,
+            _ => return None,
+        };
+        Some(cmp)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub reg: Reg,
+    pub cmp: Cmp,
+    pub val: u16,
+}
+
+impl Condition {
+    fn check(&self, rf: &RegisterFile) -> bool {
+        let reg = self.reg.read(rf);
+        match self.cmp {
+            Cmp::Eq => reg == self.val,
+            Cmp::Ne => reg != self.val,
+            Cmp::Lt => reg < self.val,
+            Cmp::Le => reg <= self.val,
+            Cmp::Gt => reg > self.val,
+            Cmp::Ge => reg >= self.val,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub cond: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access, // either
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16, // inclusive
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn hit(&self, access: &MemAccess) -> bool {
+        let kind = match self.kind {
+            WatchKind::Read => !access.write,
+            WatchKind::Write => access.write,
+            WatchKind::Access => true,
+        };
+        kind && (self.start..=self.end).contains(&access.addr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakReason {
+    Pause,
+    Step,
+    Breakpoint(u16),
+    Watchpoint(MemAccess),
+    VBlank,
+    Locked(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Running,
+    Paused,
+    Step,
+    StepOver { ret: u16, sp: u16 },
+    StepOut { sp: u16 },
+    ToVBlank,
+}
+
+// what the cpu looked like before the step we're checking
+#[derive(Debug, Clone, Copy)]
+pub struct StepInfo {
+    pub opcode: u8,
+    pub state: CpuState,
+    pub vblank: bool,
+}
+
+impl StepInfo {
+    pub fn new(cpu: &Cpu) -> Self {
+        Self {
+            opcode: cpu.bus.peek(cpu.registers().pc),
+            state: cpu.state(),
+            vblank: cpu.bus.ppu.in_vblank(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub reason: Option<BreakReason>, // why we last stopped
+    pub symbols: Option<Symbols>,
+    mode: RunMode,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: vec![],
+            watchpoints: vec![],
+            reason: None,
+            symbols: None,
+            mode: RunMode::Running,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == RunMode::Paused
+    }
+
+    // nothing to check after each step, lets the emulator skip the bookkeeping
+    pub fn is_idle(&self) -> bool {
+        self.mode == RunMode::Running && self.breakpoints.is_empty() && self.watchpoints.is_empty()
+    }
+
+    pub fn pause(&mut self) {
+        self.stop(BreakReason::Pause);
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = RunMode::Running;
+    }
+
+    pub fn step_into(&mut self) {
+        self.mode = RunMode::Step;
+    }
+
+    // runs a CALL or RST until it returns, anything else is a normal step
+    pub fn step_over(&mut self, cpu: &Cpu) {
+        let rf = cpu.registers();
+        let len = match cpu.bus.peek(rf.pc) {
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => 3,
+            op if op & 0xC7 == 0xC7 => 1, // RST
+            _ => return self.step_into(),
+        };
+        self.mode = RunMode::StepOver {
+            ret: rf.pc.wrapping_add(len),
+            sp: rf.sp,
+        };
+    }
+
+    // runs until a return pops the stack above where it is now
+    pub fn step_out(&mut self, cpu: &Cpu) {
+        self.mode = RunMode::StepOut {
+            sp: cpu.registers().sp,
+        };
+    }
+
+    pub fn run_to_vblank(&mut self) {
+        self.mode = RunMode::ToVBlank;
+    }
+
+    fn stop(&mut self, reason: BreakReason) {
+        self.mode = RunMode::Paused;
+        self.reason = Some(reason);
+    }
+
+    pub fn after_step(&mut self, cpu: &Cpu, info: StepInfo) {
+        if self.mode == RunMode::Paused {
+            return;
+        }
+        let rf = cpu.registers();
+
+        if let CpuState::Locked(pc) = cpu.state()
+            && info.state != cpu.state()
+        {
+            return self.stop(BreakReason::Locked(pc));
+        }
+        if let Some(log) = &cpu.access_log
+            && let Some(access) = log
+                .iter()
+                .find(|a| self.watchpoints.iter().any(|w| w.hit(a)))
+        {
+            return self.stop(BreakReason::Watchpoint(*access));
+        }
+        if self
+            .breakpoints
+            .iter()
+            .any(|b| b.addr == rf.pc && b.cond.is_none_or(|c| c.check(rf)))
+        {
+            return self.stop(BreakReason::Breakpoint(rf.pc));
+        }
+
+        let done = match self.mode {
+            RunMode::Step => true,
+            RunMode::StepOver { ret, sp } => rf.pc == ret && rf.sp >= sp,
+            RunMode::StepOut { sp } => {
+                matches!(info.opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9) && rf.sp > sp
+            }
+            RunMode::ToVBlank => {
+                if !info.vblank && cpu.bus.ppu.in_vblank() {
+                    return self.stop(BreakReason::VBlank);
+                }
+                false
+            }
+            RunMode::Running | RunMode::Paused => false,
+        };
+        if done {
+            self.stop(BreakReason::Step);
+        }
+    }
+}
+
+// numbers are hex by default, with or without a $ or 0x in front
+fn parse_u16(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("bad number: {s}"))
+}
+
+pub fn format_registers(rf: &RegisterFile) -> String {
+    format!(
+        "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} [{}{}{}{}]",
+        rf.read_af(),
+        rf.read_bc(),
+        rf.read_de(),
+        rf.read_hl(),
+        rf.sp,
+        rf.pc,
+        if rf.read_z() { 'Z' } else { '-' },
+        if rf.read_n() { 'N' } else { '-' },
+        if rf.read_h() { 'H' } else { '-' },
+        if rf.read_c() { 'C' } else { '-' },
+    )
+}
+
+pub fn format_reason(reason: BreakReason) -> String {
+    match reason {
+        BreakReason::Pause => "paused".to_string(),
+        BreakReason::Step => "step".to_string(),
+        BreakReason::Breakpoint(addr) => format!("breakpoint at ${addr:04X}"),
+        BreakReason::Watchpoint(a) => format!(
+            "watchpoint: {} ${:02X} {} ${:04X}",
+            if a.write { "wrote" } else { "read" },
+            a.val,
+            if a.write { "to" } else { "from" },
+            a.addr
+        ),
+        BreakReason::VBlank => "vblank".to_string(),
+        BreakReason::Locked(pc) => format!("CPU locked at ${pc:04X}"),
+    }
+}
+
+pub const HELP: &str = "\
+c                     continue
+p                     pause
+s                     step into
+n                     step over
+o                     step out
+v                     run to vblank
+b ADDR [REG OP VAL]   add breakpoint, e.g. `b 0150 a == 3f`
+w START[-END] [r|w]   add watchpoint on reads, writes or both
+d b|w N               delete breakpoint/watchpoint N
+l                     list breakpoints and watchpoints
+r                     show registers
+set REG VAL           change a register
+x ADDR [LEN]          dump memory
+u [ADDR] [N]          disassemble N instructions, from PC by default
+q                     quit (repl only), ctrl-c pauses while running";
+
+// one debugger command, shared by the repl and the gui panel. anything that starts running
+// just sets the mode, the caller is the one actually running the emulator
+pub fn command(gb: &mut Gameboy, line: &str) -> Result<String, String> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let Some(&cmd) = args.first() else {
+        return Ok(String::new());
+    };
+    let dbg = &mut gb.debugger;
+
+    match cmd {
+        "c" | "continue" => dbg.resume(),
+        "p" | "pause" => dbg.pause(),
+        "s" | "step" => dbg.step_into(),
+        "n" | "next" => dbg.step_over(&gb.cpu),
+        "o" | "out" => dbg.step_out(&gb.cpu),
+        "v" | "vblank" => dbg.run_to_vblank(),
+        "b" | "break" => {
+            let addr = parse_u16(args.get(1).ok_or("missing address")?)?;
+            let cond = match &args[2..] {
+                [] => None,
+                [reg, cmp, val] => Some(Condition {
+                    reg: Reg::parse(reg).ok_or(format!("bad register: {reg}"))?,
+                    cmp: Cmp::parse(cmp).ok_or(format!("bad comparison: {cmp}"))?,
+                    val: parse_u16(val)?,
+                }),
+                _ => return Err("condition should look like `a == 3f`".to_string()),
+            };
+            dbg.breakpoints.push(Breakpoint { addr, cond });
+            return Ok(format!("breakpoint {}", dbg.breakpoints.len() - 1));
+        }
+        "w" | "watch" => {
+            let range = args.get(1).ok_or("missing address")?;
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (parse_u16(start)?, parse_u16(end)?),
+                None => (parse_u16(range)?, parse_u16(range)?),
+            };
+            let kind = match args.get(2).copied() {
+                None | Some("rw") => WatchKind::Access,
+                Some("r") => WatchKind::Read,
+                Some("w") => WatchKind::Write,
+                Some(k) => return Err(format!("bad watch kind: {k}")),
+            };
+            dbg.watchpoints.push(Watchpoint { start, end, kind });
+            return Ok(format!("watchpoint {}", dbg.watchpoints.len() - 1));
+        }
+        "d" | "delete" => {
+            let n: usize = args
+                .get(2)
+                .and_then(|n| n.parse().ok())
+                .ok_or("missing index")?;
+            let removed = match args.get(1).copied() {
+                Some("b") if n < dbg.breakpoints.len() => dbg.breakpoints.remove(n).addr,
+                Some("w") if n < dbg.watchpoints.len() => dbg.watchpoints.remove(n).start,
+                _ => return Err("no such breakpoint/watchpoint".to_string()),
+            };
+            return Ok(format!("deleted ${removed:04X}"));
+        }
+        "l" | "list" => {
+            let mut out = String::new();
+            for (i, b) in dbg.breakpoints.iter().enumerate() {
+                out += &format!("b {i}: ${:04X}", b.addr);
+                if let Some(c) = b.cond {
+                    out += &format!(" if {:?} {:?} ${:04X}", c.reg, c.cmp, c.val);
+                }
+                out += "\n";
+            }
+            for (i, w) in dbg.watchpoints.iter().enumerate() {
+                out += &format!("w {i}: ${:04X}-${:04X} {:?}\n", w.start, w.end, w.kind);
+            }
+            return Ok(out);
+        }
+        "r" | "regs" => return Ok(format_registers(gb.cpu.registers())),
+        "set" => {
+            let [_, reg, val] = args[..] else {
+                return Err("usage: set REG VAL".to_string());
+            };
+            let reg = Reg::parse(reg).ok_or(format!("bad register: {reg}"))?;
+            reg.write(gb.cpu.registers_mut(), parse_u16(val)?);
+            return Ok(format_registers(gb.cpu.registers()));
+        }
+        "x" | "examine" => {
+            let addr = parse_u16(args.get(1).ok_or("missing address")?)?;
+            let len = match args.get(2) {
+                Some(len) => parse_u16(len)? as u32,
+                None => 0x10,
+            };
+            // u32 so the last row of a dump that goes up to $FFFF doesn't overflow
+            let mut out = String::new();
+            for row in (0..len).step_by(16) {
+                let row_addr = addr.wrapping_add(row as u16);
+                out += &format!("{row_addr:04X}:");
+                for i in row..len.min(row + 16) {
+                    out += &format!(" {:02X}", gb.cpu.bus.peek(addr.wrapping_add(i as u16)));
+                }
+                out += "\n";
+            }
+            return Ok(out);
+        }
+        "u" | "dis" => {
+            let mut addr = match args.get(1) {
+                Some(addr) => parse_u16(addr)?,
+                None => gb.cpu.registers().pc,
+            };
+            let count = match args.get(2) {
+                Some(n) => parse_u16(n)?,
+                None => 8,
+            };
+            let mut out = String::new();
+            for _ in 0..count {
+                let inst = disasm::decode_bus(&gb.cpu.bus, addr, dbg.symbols.as_ref());
+                out += &format!("{inst}\n");
+                addr = addr.wrapping_add(inst.len());
+            }
+            return Ok(out);
+        }
+        "h" | "help" => return Ok(HELP.to_string()),
+        _ => return Err(format!("unknown command: {cmd}, try `help`")),
+    }
+    Ok(String::new())
+}
+
+pub fn current_instruction(gb: &Gameboy) -> String {
+    let pc = gb.cpu.registers().pc;
+    disasm::decode_bus(&gb.cpu.bus, pc, gb.debugger.symbols.as_ref()).to_string()
+}
+
+fn print_position(gb: &Gameboy) {
+    println!("{}", format_registers(gb.cpu.registers()));
+    println!("{}", current_instruction(gb));
+}
+
+// command line front end for the debugger, runs without the gui
+pub fn repl(gb: &mut Gameboy) {
+    gb.debugger.pause();
+    print_position(gb);
+
+    // ctrl-c pauses instead of killing the emulator, which would lose the save too
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    if let Err(e) = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed)) {
+        println!("WARNING: couldn't catch ctrl-c: {e}");
+    }
+
+    let mut last = String::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(gb) ");
+        io::stdout().flush().unwrap();
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        // an empty line repeats the last command, handy for stepping
+        let line = if line.trim().is_empty() {
+            last.clone()
+        } else {
+            line.trim().to_string()
+        };
+        if line == "q" || line == "quit" {
+            break;
+        }
+
+        match command(gb, &line) {
+            Ok(out) if !out.is_empty() => println!("{}", out.trim_end()),
+            Ok(_) => (),
+            Err(e) => println!("error: {e}"),
+        }
+        if !gb.debugger.is_paused() {
+            interrupted.store(false, Ordering::Relaxed);
+            while !gb.debugger.is_paused() {
+                if interrupted.swap(false, Ordering::Relaxed) {
+                    gb.debugger.pause();
+                    break;
+                }
+                gb.step_instruction();
+            }
+            if let Some(reason) = gb.debugger.reason {
+                println!("{}", format_reason(reason));
+            }
+            print_position(gb);
+        }
+        last = line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::test_gameboy;
+
+    #[test]
+    fn examine_whole_address_space() {
+        // MBC1 so all of $A000-$BFFF reads fine without any ram
+        let mut gb = test_gameboy(0x01, &[0x18, 0xFE]);
+        let out = command(&mut gb, "x 0 FFFF").unwrap();
+        let rows: Vec<&str> = out.lines().collect();
+        assert_eq!(rows.len(), 0x1000);
+        assert!(rows[0].starts_with("0000: 00 00"));
+        // FFFF bytes, so the last row stops one short of IE
+        assert!(rows[0xFFF].starts_with("FFF0:"));
+        assert_eq!(rows[0xFFF].split_whitespace().count(), 16);
+        assert_eq!(rows[0x10].split_whitespace().nth(1), Some("18"));
+    }
+
+    #[test]
+    fn examine_wraps_around() {
+        let mut gb = test_gameboy(0x01, &[]);
+        let out = command(&mut gb, "x FFF8 10").unwrap();
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.starts_with("FFF8:"));
+        assert_eq!(out.split_whitespace().count(), 17);
+    }
+
+    #[test]
+    fn read_watchpoint_ignores_code() {
+        #[rustfmt::skip]
+        let code = [
+            0x00,             // nop
+            0xFA, 0x00, 0x01, // ld a, ($0100)
+            0x18, 0xFE,       // jr -2
+        ];
+        let mut gb = test_gameboy(0x01, &code);
+        command(&mut gb, "w 100-105 r").unwrap();
+        gb.step_instruction();
+        assert!(!gb.debugger.is_paused());
+        gb.step_instruction();
+        assert_eq!(
+            gb.debugger.reason,
+            Some(BreakReason::Watchpoint(MemAccess {
+                addr: 0x100,
+                val: 0x00,
+                write: false
+            }))
+        );
+    }
+
+    #[test]
+    fn examine_during_dma() {
+        let mut gb = test_gameboy(0x01, &[]);
+        gb.cpu.bus.write_u8(0xC000, 0x42);
+        gb.cpu.bus.write_u8(0xFF46, 0xC0);
+        gb.cpu.bus.tick(8);
+        assert_eq!(gb.cpu.bus.read_u8(0xC000), 0xFF);
+        assert!(
+            command(&mut gb, "x C000 1")
+                .unwrap()
+                .starts_with("C000: 42")
+        );
+    }
+}
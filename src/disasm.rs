@@ -0,0 +1,402 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::bus::Bus;
+
+const R8: [&str; 8] = ["B", "C", "D", "E", "H", "L", "[HL]", "A"];
+const R16: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const R16_STACK: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const R16_IND: [&str; 4] = ["[BC]", "[DE]", "[HL+]", "[HL-]"];
+const COND: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB", "SBC A,", "AND", "XOR", "OR", "CP",
+];
+const CB_SHIFT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+// labels from an RGBDS .sym file, lines look like `01:4000 SomeLabel`
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: HashMap<u16, Vec<(usize, String)>>,
+}
+
+impl Symbols {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut labels: HashMap<u16, Vec<(usize, String)>> = HashMap::new();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let Some((loc, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, addr)) = loc.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(addr)) = (
+                usize::from_str_radix(bank, 16),
+                u16::from_str_radix(addr, 16),
+            ) else {
+                continue;
+            };
+            labels
+                .entry(addr)
+                .or_default()
+                .push((bank, name.trim().to_string()));
+        }
+        Self { labels }
+    }
+
+    // only the switchable rom area really cares about the bank, anywhere else just take
+    // whatever label is there
+    pub fn lookup(&self, bank: usize, addr: u16) -> Option<&str> {
+        let labels = self.labels.get(&addr)?;
+        let label = match addr {
+            0x4000..=0x7FFF => labels.iter().find(|(b, _)| *b == bank)?,
+            _ => labels.first()?,
+        };
+        Some(&label.1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub bank: usize,
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Instruction {
+    pub fn len(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{b:02X}")).collect();
+        write!(
+            f,
+            "{:02X}:{:04X}  {:<9} {}",
+            self.bank,
+            self.addr,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+// decodes whatever is at addr in the current memory map, banks are whatever the mbc has
+// selected right now
+pub fn decode_bus(bus: &Bus, addr: u16, symbols: Option<&Symbols>) -> Instruction {
+    decode(|a| bus.peek(a), addr, bus.rom_bank(addr), symbols)
+}
+
+// decodes the instruction at addr out of a raw rom file, bank 0 lives at 0000-3FFF and every
+// other bank at 4000-7FFF
+pub fn decode_rom(rom: &[u8], bank: usize, addr: u16, symbols: Option<&Symbols>) -> Instruction {
+    let read = |a: u16| {
+        let offset = match a {
+            0x0000..=0x3FFF => a as usize,
+            _ => (bank.max(1) << 14) | (a as usize & 0x3FFF),
+        };
+        rom.get(offset).copied().unwrap_or(0xFF)
+    };
+    decode(read, addr, bank, symbols)
+}
+
+pub fn decode(
+    read: impl Fn(u16) -> u8,
+    addr: u16,
+    bank: usize,
+    symbols: Option<&Symbols>,
+) -> Instruction {
+    let mut d = Decoder {
+        read,
+        bank,
+        symbols,
+        pc: addr,
+    };
+    let text = d.instruction();
+    let bytes = (0..d.pc.wrapping_sub(addr))
+        .map(|i| (d.read)(addr.wrapping_add(i)))
+        .collect();
+    Instruction {
+        bank,
+        addr,
+        bytes,
+        text,
+    }
+}
+
+struct Decoder<'a, F: Fn(u16) -> u8> {
+    read: F,
+    bank: usize,
+    symbols: Option<&'a Symbols>,
+    pc: u16,
+}
+
+impl<F: Fn(u16) -> u8> Decoder<'_, F> {
+    fn fetch_u8(&mut self) -> u8 {
+        let val = (self.read)(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        val
+    }
+
+    fn fetch_u16(&mut self) -> u16 {
+        let lo = self.fetch_u8() as u16;
+        let hi = self.fetch_u8() as u16;
+        (hi << 8) | lo
+    }
+
+    fn label(&self, addr: u16) -> Option<&str> {
+        self.symbols?.lookup(self.bank, addr)
+    }
+
+    // an address operand, as a label if we have one
+    fn target(&self, addr: u16) -> String {
+        match self.label(addr) {
+            Some(label) => label.to_string(),
+            None => format!("${addr:04X}"),
+        }
+    }
+
+    fn imm8(&mut self) -> String {
+        format!("${:02X}", self.fetch_u8())
+    }
+
+    fn imm16(&mut self) -> String {
+        format!("${:04X}", self.fetch_u16())
+    }
+
+    fn addr16(&mut self) -> String {
+        let addr = self.fetch_u16();
+        self.target(addr)
+    }
+
+    fn offset(&mut self) -> String {
+        let e = self.fetch_u8() as i8;
+        match e < 0 {
+            true => format!("-${:02X}", e.unsigned_abs()),
+            false => format!("+${e:02X}"),
+        }
+    }
+
+    // same grouping as Cpu::execute_instruction
+    fn instruction(&mut self) -> String {
+        let opcode = self.fetch_u8();
+        let r16 = R16[(opcode >> 4) as usize & 3];
+        let cond = COND[(opcode >> 3) as usize & 3];
+        match opcode {
+            //misc
+            0x00 => "NOP".to_string(),
+            0xCB => self.cb_instruction(),
+            0xF3 => "DI".to_string(),
+            0xFB => "EI".to_string(),
+            0x10 => format!("STOP {}", self.imm8()),
+            0x76 => "HALT".to_string(),
+            // loads
+            0x01 | 0x11 | 0x21 | 0x31 => format!("LD {r16}, {}", self.imm16()),
+            0x06 | 0x16 | 0x26 | 0x36 | 0x0E | 0x1E | 0x2E | 0x3E => {
+                format!("LD {}, {}", R8[(opcode >> 3) as usize], self.imm8())
+            }
+            0x02 | 0x12 | 0x22 | 0x32 => format!("LD {}, A", R16_IND[(opcode >> 4) as usize]),
+            0x0A | 0x1A | 0x2A | 0x3A => format!("LD A, {}", R16_IND[(opcode >> 4) as usize]),
+            0x40..=0x75 | 0x77..=0x7F => format!(
+                "LD {}, {}",
+                R8[(opcode >> 3) as usize & 7],
+                R8[opcode as usize & 7]
+            ),
+            0x08 => format!("LD [{}], SP", self.addr16()),
+            0xF9 => "LD SP, HL".to_string(),
+            0xC1 | 0xD1 | 0xE1 | 0xF1 => format!("POP {}", R16_STACK[(opcode >> 4) as usize & 3]),
+            0xC5 | 0xD5 | 0xE5 | 0xF5 => format!("PUSH {}", R16_STACK[(opcode >> 4) as usize & 3]),
+            0xF8 => format!("LD HL, SP{}", self.offset()),
+            0xE0 => format!("LDH [{}], A", self.high_addr()),
+            0xF0 => format!("LDH A, [{}]", self.high_addr()),
+            0xE2 => "LDH [C], A".to_string(),
+            0xF2 => "LDH A, [C]".to_string(),
+            0xEA => format!("LD [{}], A", self.addr16()),
+            0xFA => format!("LD A, [{}]", self.addr16()),
+            // math
+            0x07 => "RLCA".to_string(),
+            0x17 => "RLA".to_string(),
+            0x0F => "RRCA".to_string(),
+            0x1F => "RRA".to_string(),
+            0x03 | 0x13 | 0x23 | 0x33 => format!("INC {r16}"),
+            0x04 | 0x14 | 0x24 | 0x34 | 0x0C | 0x1C | 0x2C | 0x3C => {
+                format!("INC {}", R8[(opcode >> 3) as usize])
+            }
+            0x0B | 0x1B | 0x2B | 0x3B => format!("DEC {r16}"),
+            0x05 | 0x15 | 0x25 | 0x35 | 0x0D | 0x1D | 0x2D | 0x3D => {
+                format!("DEC {}", R8[(opcode >> 3) as usize])
+            }
+            0x09 | 0x19 | 0x29 | 0x39 => format!("ADD HL, {r16}"),
+            0x80..=0xBF => format!(
+                "{} {}",
+                ALU[(opcode >> 3) as usize & 7],
+                R8[opcode as usize & 7]
+            ),
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+                format!("{} {}", ALU[(opcode >> 3) as usize & 7], self.imm8())
+            }
+            0x27 => "DAA".to_string(),
+            0x37 => "SCF".to_string(),
+            0x2F => "CPL".to_string(),
+            0x3F => "CCF".to_string(),
+            0xE8 => format!("ADD SP, {}", self.offset()),
+            // branches
+            0x18 | 0x28 | 0x38 | 0x20 | 0x30 => {
+                let e = self.fetch_u8() as i8;
+                let dest = self.target(self.pc.wrapping_add_signed(e as i16));
+                match opcode {
+                    0x18 => format!("JR {dest}"),
+                    _ => format!("JR {cond}, {dest}"),
+                }
+            }
+            0xC0 | 0xD0 | 0xC8 | 0xD8 => format!("RET {cond}"),
+            0xC9 => "RET".to_string(),
+            0xD9 => "RETI".to_string(),
+            0xC2 | 0xD2 | 0xCA | 0xDA => format!("JP {cond}, {}", self.addr16()),
+            0xC3 => format!("JP {}", self.addr16()),
+            0xE9 => "JP HL".to_string(),
+            0xC4 | 0xD4 | 0xCC | 0xDC => format!("CALL {cond}, {}", self.addr16()),
+            0xCD => format!("CALL {}", self.addr16()),
+            0xC7 | 0xD7 | 0xE7 | 0xF7 | 0xCF | 0xDF | 0xEF | 0xFF => {
+                format!("RST ${:02X}", opcode & 0x38)
+            }
+            // the 11 undefined opcodes
+            _ => format!("DB ${opcode:02X}"),
+        }
+    }
+
+    fn high_addr(&mut self) -> String {
+        let addr = 0xFF00 | self.fetch_u8() as u16;
+        self.target(addr)
+    }
+
+    fn cb_instruction(&mut self) -> String {
+        let opcode = self.fetch_u8();
+        let r8 = R8[opcode as usize & 7];
+        let bit = (opcode >> 3) & 7;
+
+        match opcode {
+            0x00..=0x3F => format!("{} {r8}", CB_SHIFT[(opcode >> 3) as usize]),
+            0x40..=0x7F => format!("BIT {bit}, {r8}"),
+            0x80..=0xBF => format!("RES {bit}, {r8}"),
+            0xC0..=0xFF => format!("SET {bit}, {r8}"),
+        }
+    }
+}
+
+// disassembles a whole rom file (or just one bank of it), labels go on their own line
+pub fn disasm_rom(
+    out: &mut impl Write,
+    rom: &[u8],
+    bank: Option<usize>,
+    symbols: Option<&Symbols>,
+) -> io::Result<()> {
+    let banks = match bank {
+        Some(bank) => bank..bank + 1,
+        None => 0..rom.len().div_ceil(0x4000),
+    };
+    for bank in banks {
+        let (start, end) = match bank {
+            0 => (0x0000u32, 0x4000u32),
+            _ => (0x4000, 0x8000),
+        };
+        let mut addr = start;
+        while addr < end && (bank << 14) + (addr as usize & 0x3FFF) < rom.len() {
+            if let Some(label) = symbols.and_then(|s| s.lookup(bank, addr as u16)) {
+                writeln!(out, "{label}:")?;
+            }
+            let inst = decode_rom(rom, bank, addr as u16, symbols);
+            writeln!(out, "{inst}")?;
+            addr += inst.len() as u32;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_bytes(bytes: &[u8]) -> Instruction {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0150 + bytes.len()].copy_from_slice(bytes);
+        decode_rom(&rom, 0, 0x0150, None)
+    }
+
+    #[test]
+    fn decodes_opcodes() {
+        for (bytes, text) in [
+            (&[0x00][..], "NOP"),
+            (&[0x3E, 0x42], "LD A, $42"),
+            (&[0x01, 0x34, 0x12], "LD BC, $1234"),
+            (&[0xEA, 0x00, 0xC0], "LD [$C000], A"),
+            (&[0xF0, 0x44], "LDH A, [$FF44]"),
+            (&[0xE8, 0xFE], "ADD SP, -$02"),
+            (&[0x18, 0xFE], "JR $0150"),
+            (&[0x20, 0x05], "JR NZ, $0157"),
+            (&[0xCD, 0x00, 0x40], "CALL $4000"),
+            (&[0xFE, 0x90], "CP $90"),
+            (&[0xFF], "RST $38"),
+            (&[0x10, 0x00], "STOP $00"),
+            (&[0xD3], "DB $D3"),
+            (&[0xCB, 0x37], "SWAP A"),
+            (&[0xCB, 0x7E], "BIT 7, [HL]"),
+            (&[0xCB, 0x80], "RES 0, B"),
+            (&[0xCB, 0xFF], "SET 7, A"),
+        ] {
+            let inst = decode_bytes(bytes);
+            assert_eq!(inst.text, text);
+            assert_eq!(inst.bytes, bytes);
+        }
+    }
+
+    #[test]
+    fn parses_symbols() {
+        let symbols = Symbols::parse(
+            "; a comment\n\
+             00:0150 Start\n\
+             01:4000 BankOne ; trailing comment\n\
+             02:4000 BankTwo\n\
+             00:C000 wBuffer\n\
+             junk line\n",
+        );
+        assert_eq!(symbols.lookup(0, 0x0150), Some("Start"));
+        assert_eq!(symbols.lookup(1, 0x4000), Some("BankOne"));
+        assert_eq!(symbols.lookup(2, 0x4000), Some("BankTwo"));
+        assert_eq!(symbols.lookup(3, 0x4000), None);
+        // outside the switchable rom the bank doesn't matter
+        assert_eq!(symbols.lookup(5, 0xC000), Some("wBuffer"));
+    }
+
+    #[test]
+    fn labels_use_the_bank() {
+        let symbols = Symbols::parse("01:4000 BankOne\n02:4000 BankTwo\n");
+        let mut rom = vec![0; 0xC000];
+        rom[0x4000..0x4003].copy_from_slice(&[0xCD, 0x00, 0x40]);
+        rom[0x8000..0x8003].copy_from_slice(&[0xCD, 0x00, 0x40]);
+        assert_eq!(
+            decode_rom(&rom, 1, 0x4000, Some(&symbols)).text,
+            "CALL BankOne"
+        );
+        assert_eq!(
+            decode_rom(&rom, 2, 0x4000, Some(&symbols)).text,
+            "CALL BankTwo"
+        );
+
+        let mut out = vec![];
+        disasm_rom(&mut out, &rom, Some(2), Some(&symbols)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("BankTwo:\n02:4000  CD 00 40  CALL BankTwo\n"));
+    }
+}
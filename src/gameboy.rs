@@ -3,9 +3,14 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    cpu::Cpu,
-    mbc::create_cart,
+    apu::Apu,
+    cpu::{Cpu, CpuState},
+    debugger::{Debugger, StepInfo},
+    input::{Held, InputLayer},
+    mbc::{Mbc, create_cart},
     ppu::{Color, SCREEN_HEIGHT, SCREEN_WIDTH},
 };
 
@@ -14,11 +19,16 @@ const CYCLES_PER_FRAME: u64 = CLOCK_SPEED / 60;
 
 pub struct Gameboy {
     pub cpu: Cpu,
+    pub debugger: Debugger,
+    pub save_on_exit: bool, // off for movie playback, which brings its own save
+    pub held: Held,         // what the frontend has held down, goes through `input` every frame
+    pub input: InputLayer,
     cycles: u64,
+    frame_started: bool, // the debugger stopped partway through a frame
     save_path: PathBuf,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GbBtn {
     Up,
     Down,
@@ -30,27 +40,114 @@ pub enum GbBtn {
     Start,
 }
 
+pub const BUTTONS: [GbBtn; 8] = [
+    GbBtn::Up,
+    GbBtn::Down,
+    GbBtn::Left,
+    GbBtn::Right,
+    GbBtn::A,
+    GbBtn::B,
+    GbBtn::Select,
+    GbBtn::Start,
+];
+
+impl GbBtn {
+    // its bit in Gameboy::buttons
+    pub fn mask(self) -> u8 {
+        1 << BUTTONS.iter().position(|&b| b == self).unwrap()
+    }
+}
+
 impl Gameboy {
     pub fn new(rom_path: &Path) -> Self {
-        let save_path = rom_path.with_extension("sav");
+        Self::with_save(rom_path, rom_path.with_extension("sav"), Apu::new())
+    }
+
+    // for running the same rom twice without both writing to one save file or both playing sound
+    pub fn with_save(rom_path: &Path, save_path: PathBuf, apu: Apu) -> Self {
         let rom: Vec<u8> = fs::read(rom_path).unwrap();
         let save = fs::read(&save_path).ok();
-        let cart = create_cart(rom, save);
-        let mut cpu = Cpu::new(cart);
+        Self::from_cart(create_cart(rom, save), save_path, apu)
+    }
+
+    pub fn from_cart(cart: Box<dyn Mbc>, save_path: PathBuf, apu: Apu) -> Self {
+        let mut cpu = Cpu::new(cart, apu);
         cpu.simulate_boot();
         Self {
             cpu,
+            debugger: Debugger::new(),
+            save_on_exit: true,
+            held: Held::default(),
+            input: InputLayer::default(),
             cycles: 0,
+            frame_started: false,
             save_path,
         }
     }
 
-    pub fn run_frame(&mut self) {
+    // returns whether the frame got finished, the debugger can stop it partway
+    pub fn run_frame(&mut self) -> bool {
+        self.start_frame();
         while self.cycles < CYCLES_PER_FRAME {
-            let cycles = self.cpu.tick();
+            // stopping mid frame keeps the cycles we've done so far for when we resume
+            if self.debugger.is_paused() {
+                return false;
+            }
+            let cycles = self.step_instruction();
             self.cycles += cycles;
         }
         self.cycles -= CYCLES_PER_FRAME;
+        self.frame_started = false;
+        true
+    }
+
+    // the input layer counts frames, so it only moves on when a new one starts and not when one
+    // the debugger stopped partway through gets picked back up
+    fn start_frame(&mut self) {
+        if !self.frame_started {
+            self.frame_started = true;
+            let buttons = self.input.apply(&self.held);
+            self.set_buttons(buttons);
+        }
+    }
+
+    // runs two gameboys for a frame, always stepping whichever one is behind so they're never
+    // more than an instruction apart. that's as finely as they can be interleaved, a LocalLink
+    // between them finishes both ends of a transfer within that instruction
+    pub fn run_frame_linked(&mut self, other: &mut Gameboy) {
+        self.start_frame();
+        other.start_frame();
+        while self.cycles < CYCLES_PER_FRAME || other.cycles < CYCLES_PER_FRAME {
+            if self.debugger.is_paused() || other.debugger.is_paused() {
+                return;
+            }
+            let gb = if self.cycles <= other.cycles {
+                &mut *self
+            } else {
+                &mut *other
+            };
+            gb.cycles += gb.step_instruction();
+        }
+        self.cycles -= CYCLES_PER_FRAME;
+        other.cycles -= CYCLES_PER_FRAME;
+        self.frame_started = false;
+        other.frame_started = false;
+    }
+
+    // runs one instruction (or interrupt dispatch) and lets the debugger look at it. returns
+    // normal speed cycles, so a frame is the same length in CGB double speed
+    pub fn step_instruction(&mut self) -> u64 {
+        let double_speed = self.cpu.bus.double_speed;
+        let cycles = if self.debugger.is_idle() {
+            self.cpu.tick()
+        } else {
+            let info = StepInfo::new(&self.cpu);
+            self.cpu.access_log = (!self.debugger.watchpoints.is_empty()).then(Vec::new);
+            let cycles = self.cpu.tick();
+            self.debugger.after_step(&self.cpu, info);
+            cycles
+        };
+        cycles >> double_speed as u32
     }
 
     pub fn set_button(&mut self, button: GbBtn, down: bool) {
@@ -66,6 +163,38 @@ impl Gameboy {
         }
     }
 
+    // one bit per button, in the same order as BUTTONS
+    pub fn buttons(&self) -> u8 {
+        let joypad = &self.cpu.bus.joypad;
+        [
+            joypad.up,
+            joypad.down,
+            joypad.left,
+            joypad.right,
+            joypad.a,
+            joypad.b,
+            joypad.select,
+            joypad.start,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (i, &down)| mask | (down as u8) << i)
+    }
+
+    pub fn set_buttons(&mut self, mask: u8) {
+        for (i, button) in BUTTONS.into_iter().enumerate() {
+            self.set_button(button, mask & (1 << i) != 0);
+        }
+    }
+
+    pub fn set_access_blocking(&mut self, enabled: bool) {
+        self.cpu.bus.ppu.access_blocking = enabled;
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
     pub fn get_frame(&self) -> &[[Color; SCREEN_WIDTH]; SCREEN_HEIGHT] {
         &self.cpu.bus.ppu.frame
     }
@@ -73,8 +202,19 @@ impl Gameboy {
 
 impl Drop for Gameboy {
     fn drop(&mut self) {
-        if self.cpu.bus.cart.has_battery() {
+        if self.save_on_exit && self.cpu.bus.cart.has_battery() {
             fs::write(&self.save_path, self.cpu.bus.cart.dump_ram()).unwrap();
         }
     }
 }
+
+// a 32KB rom with `code` at the entry point, for tests that need something to run
+#[cfg(test)]
+pub fn test_gameboy(cart_type: u8, code: &[u8]) -> Gameboy {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0147] = cart_type;
+    rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+    let mut gb = Gameboy::from_cart(create_cart(rom, None), PathBuf::new(), Apu::muted());
+    gb.save_on_exit = false;
+    gb
+}
@@ -0,0 +1,400 @@
+use std::{
+    io::{self, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::debugger::{BreakReason, Breakpoint, Reg, WatchKind, Watchpoint};
+use crate::gameboy::Gameboy;
+
+// registers in the order gdb's z80 target has them, everything after PC is z80 only and left out
+const REGS: [Reg; 6] = [Reg::Af, Reg::Bc, Reg::De, Reg::Hl, Reg::Sp, Reg::Pc];
+
+// how many instructions to run between checking if gdb wants us to stop
+const POLL_INTERVAL: u32 = 4096;
+
+// gdb remote serial protocol stub, see https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+// breakpoints and watchpoints go through the debugger, so nothing gets patched into memory
+pub fn serve(gb: &mut Gameboy, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("waiting for gdb on 127.0.0.1:{port}");
+    serve_on(gb, listener)
+}
+
+// split out so tests can listen on whatever port is free
+fn serve_on(gb: &mut Gameboy, listener: TcpListener) -> io::Result<()> {
+    let (stream, addr) = listener.accept()?;
+    println!("gdb connected from {addr}");
+
+    gb.debugger.pause();
+    let mut conn = Connection::new(stream)?;
+    while let Some(packet) = conn.read_packet()? {
+        match handle(gb, &mut conn, &packet)? {
+            Some(reply) => conn.write_packet(&reply)?,
+            None => break,
+        }
+    }
+    println!("gdb disconnected");
+    Ok(())
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+    ack: bool, // gdb can turn acks off with QStartNoAckMode
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        // packets are tiny and every one waits on an answer, so don't let them sit around
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+            ack: true,
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // packets look like `$data#checksum`, anything outside of one (acks, stray ctrl-c) is skipped
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(_) => continue,
+            }
+        }
+        let mut data = vec![];
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(b) => data.push(b),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+
+        let expected = u8::from_str_radix(&String::from_utf8_lossy(&checksum), 16).ok();
+        if self.ack {
+            let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            let ack = if expected == Some(sum) { b"+" } else { b"-" };
+            self.stream.write_all(ack)?;
+        }
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${data}#{sum:02x}")?;
+        self.stream.flush()?;
+        if self.ack {
+            // gdb only resends on a nack, which we never get wrong enough to care about
+            self.read_byte()?;
+        }
+        Ok(())
+    }
+
+    // gdb sends a bare 0x03 to interrupt a running target
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(self.reader.buffer().contains(&0x03));
+        }
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let res = self.stream.peek(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match res {
+            Ok(1) if byte[0] == 0x03 => {
+                self.read_byte()?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn hex_u16(s: &str) -> Option<u16> {
+    u16::from_str_radix(s, 16).ok()
+}
+
+fn hex_bytes(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// 16-bit registers go over the wire little endian
+fn reg_hex(val: u16) -> String {
+    format!("{:02x}{:02x}", val & 0xFF, val >> 8)
+}
+
+fn parse_reg_hex(s: &str) -> Option<u16> {
+    let bytes = hex_bytes(s)?;
+    Some(*bytes.first()? as u16 | (*bytes.get(1)? as u16) << 8)
+}
+
+// `addr,len` as used by m, M and Z/z
+fn addr_len(s: &str) -> Option<(u16, u16)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((hex_u16(addr)?, hex_u16(len)?))
+}
+
+// runs until the debugger stops or gdb interrupts, then builds the stop reply
+fn run(gb: &mut Gameboy, conn: &mut Connection) -> io::Result<String> {
+    let mut steps = 0;
+    while !gb.debugger.is_paused() {
+        gb.step_instruction();
+        steps += 1;
+        if steps % POLL_INTERVAL == 0 && conn.interrupted()? {
+            gb.debugger.pause();
+        }
+    }
+
+    let reply = match gb.debugger.reason {
+        Some(BreakReason::Pause) => "S02".to_string(),
+        Some(BreakReason::Locked(_)) => "S04".to_string(),
+        Some(BreakReason::Breakpoint(_)) => "T05swbreak:;".to_string(),
+        Some(BreakReason::Watchpoint(access)) => {
+            let kind = gb
+                .debugger
+                .watchpoints
+                .iter()
+                .find(|w| w.hit(&access))
+                .map(|w| w.kind);
+            let name = match kind {
+                Some(WatchKind::Read) => "rwatch",
+                Some(WatchKind::Write) => "watch",
+                _ => "awatch",
+            };
+            format!("T05{name}:{:x};", access.addr)
+        }
+        _ => "S05".to_string(),
+    };
+    Ok(reply)
+}
+
+// None closes the connection
+fn handle(gb: &mut Gameboy, conn: &mut Connection, packet: &str) -> io::Result<Option<String>> {
+    let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+    let err = || "E01".to_string();
+
+    let reply = match cmd {
+        "?" => "S05".to_string(),
+        "g" => REGS.map(|r| reg_hex(r.read(gb.cpu.registers()))).concat(),
+        "G" => {
+            for (i, reg) in REGS.iter().enumerate() {
+                if let Some(val) = args.get(i * 4..i * 4 + 4).and_then(parse_reg_hex) {
+                    reg.write(gb.cpu.registers_mut(), val);
+                }
+            }
+            "OK".to_string()
+        }
+        "p" => match usize::from_str_radix(args, 16)
+            .ok()
+            .and_then(|i| REGS.get(i))
+        {
+            Some(reg) => reg_hex(reg.read(gb.cpu.registers())),
+            None => "0000".to_string(), // z80 registers we don't have
+        },
+        "P" => {
+            let parsed = args.split_once('=').and_then(|(n, val)| {
+                let reg = REGS.get(usize::from_str_radix(n, 16).ok()?)?;
+                Some((reg, parse_reg_hex(val)?))
+            });
+            match parsed {
+                Some((reg, val)) => {
+                    reg.write(gb.cpu.registers_mut(), val);
+                    "OK".to_string()
+                }
+                None => err(),
+            }
+        }
+        "m" => match addr_len(args) {
+            Some((addr, len)) => (0..len)
+                .map(|i| format!("{:02x}", gb.cpu.bus.peek(addr.wrapping_add(i))))
+                .collect(),
+            None => err(),
+        },
+        "M" => {
+            let parsed = args.split_once(':').and_then(|(range, data)| {
+                let (addr, len) = addr_len(range)?;
+                let data = hex_bytes(data)?;
+                (data.len() == len as usize).then_some((addr, data))
+            });
+            // rom can't be poked, so refuse the whole write rather than doing part of it
+            match parsed {
+                Some((addr, data))
+                    if (0..data.len()).all(|i| addr.wrapping_add(i as u16) >= 0x8000) =>
+                {
+                    for (i, val) in data.into_iter().enumerate() {
+                        gb.cpu.bus.poke(addr.wrapping_add(i as u16), val);
+                    }
+                    "OK".to_string()
+                }
+                _ => err(),
+            }
+        }
+        "c" | "s" => {
+            if let Some(addr) = hex_u16(args) {
+                gb.cpu.registers_mut().pc = addr;
+            }
+            match cmd {
+                "c" => gb.debugger.resume(),
+                _ => gb.debugger.step_into(),
+            }
+            run(gb, conn)?
+        }
+        "Z" | "z" => {
+            let mut parts = args.splitn(2, ',');
+            let kind = parts.next().unwrap_or("");
+            match (kind, parts.next().and_then(addr_len)) {
+                (_, None) => err(),
+                ("0" | "1", Some((addr, _))) => {
+                    let bp = Breakpoint { addr, cond: None };
+                    let bps = &mut gb.debugger.breakpoints;
+                    match cmd {
+                        "Z" => bps.push(bp),
+                        _ => bps.retain(|b| *b != bp),
+                    }
+                    "OK".to_string()
+                }
+                ("2" | "3" | "4", Some((addr, len))) => {
+                    let kind = match kind {
+                        "2" => WatchKind::Write,
+                        "3" => WatchKind::Read,
+                        _ => WatchKind::Access,
+                    };
+                    let wp = Watchpoint {
+                        start: addr,
+                        end: addr.wrapping_add(len.max(1) - 1),
+                        kind,
+                    };
+                    let wps = &mut gb.debugger.watchpoints;
+                    match cmd {
+                        "Z" => wps.push(wp),
+                        _ => wps.retain(|w| *w != wp),
+                    }
+                    "OK".to_string()
+                }
+                _ => String::new(),
+            }
+        }
+        "k" => return Ok(None),
+        "D" => {
+            conn.write_packet("OK")?;
+            return Ok(None);
+        }
+        "H" | "T" => "OK".to_string(),
+        "q" | "Q" => query(packet, conn),
+        // anything else is unsupported, which gdb understands as an empty reply
+        _ => String::new(),
+    };
+    Ok(Some(reply))
+}
+
+fn query(packet: &str, conn: &mut Connection) -> String {
+    let name = packet.split([':', ',']).next().unwrap_or("");
+    match name {
+        "qSupported" => "PacketSize=4000;swbreak+;hwbreak+;QStartNoAckMode+".to_string(),
+        "QStartNoAckMode" => {
+            // this one still gets acked, the next one won't
+            conn.ack = false;
+            "OK".to_string()
+        }
+        "qAttached" => "1".to_string(),
+        "qC" => "QC1".to_string(),
+        "qfThreadInfo" => "m1".to_string(),
+        "qsThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::test_gameboy;
+    use std::thread;
+
+    // sends a packet and returns gdb's view of it: the ack and the reply
+    fn exchange(stream: &mut TcpStream, data: &str) -> (u8, String) {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(stream, "${data}#{sum:02x}").unwrap();
+        let mut ack = [0];
+        stream.read_exact(&mut ack).unwrap();
+
+        let mut reply = vec![];
+        let mut byte = [0];
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'$');
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        let sum = reply.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(String::from_utf8_lossy(&checksum), format!("{sum:02x}"));
+        stream.write_all(b"+").unwrap();
+        (ack[0], String::from_utf8(reply).unwrap())
+    }
+
+    #[test]
+    fn scripted_session() {
+        // nop, nop, ld a,$42, jr -2
+        let mut gb = test_gameboy(0x00, &[0x00, 0x00, 0x3E, 0x42, 0x18, 0xFE]);
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut send = |data: &str| {
+                let (ack, reply) = exchange(&mut stream, data);
+                assert_eq!(ack, b'+', "no ack for {data}");
+                reply
+            };
+            assert!(send("qSupported:swbreak+").contains("swbreak+"));
+            assert_eq!(send("?"), "S05");
+            // AF BC DE HL SP PC, little endian
+            let regs = send("g");
+            assert_eq!(regs.len(), 24);
+            assert_eq!(&regs[16..], "feff0001");
+            assert_eq!(send("m100,4"), "00003e42");
+            assert_eq!(send("p5"), "0001");
+            // rom can't be written, not even partly
+            assert_eq!(send("M2000,1:01"), "E01");
+            assert_eq!(send("M7fff,2:0102"), "E01");
+            assert_eq!(send("Mc000,2:abcd"), "OK");
+            assert_eq!(send("mc000,2"), "abcd");
+            // oam is reachable whatever the ppu is doing
+            assert_eq!(send("Mfe00,1:55"), "OK");
+            assert_eq!(send("mfe00,1"), "55");
+            assert_eq!(send("Z0,104,1"), "OK");
+            assert_eq!(send("c"), "T05swbreak:;");
+            assert_eq!(send("p5"), "0401");
+            // F first, only Z is still set from boot
+            assert_eq!(&send("g")[..4], "8042");
+            assert_eq!(send("z0,104,1"), "OK");
+            assert_eq!(send("s"), "S05");
+            assert_eq!(send("p5"), "0401");
+            assert_eq!(send("D"), "OK");
+        });
+
+        serve_on(&mut gb, listener).unwrap();
+        client.join().unwrap();
+    }
+}
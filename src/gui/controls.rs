@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use eframe::egui;
+
+use crate::config::Config;
+use crate::gameboy::{BUTTONS, GbBtn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binding {
+    Button { player2: bool, button: GbBtn },
+    TurboA,
+    TurboB,
+}
+
+// key bindings editor, every button can have any number of keys
+pub struct ControlsView {
+    pub config: Config,
+    path: Option<PathBuf>, // None if there's no config directory to save to
+    capturing: Option<Binding>, // waiting for a key press to bind
+    status: String,
+}
+
+impl ControlsView {
+    pub fn new(config: Config, path: Option<PathBuf>) -> Self {
+        Self {
+            config,
+            path,
+            capturing: None,
+            status: String::new(),
+        }
+    }
+
+    // the gameboy shouldn't see the key that's about to get bound
+    pub fn capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, player2: bool) {
+        if let Some(binding) = self.capturing {
+            let pressed = ui.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            match pressed {
+                Some(egui::Key::Escape) => self.capturing = None,
+                Some(key) => {
+                    let keys = self.keys_mut(binding);
+                    if !keys.iter().any(|k| egui::Key::from_name(k) == Some(key)) {
+                        keys.push(key.name().to_string());
+                    }
+                    self.capturing = None;
+                }
+                None => (),
+            }
+        }
+
+        egui::Grid::new("controls").show(ui, |ui| {
+            for button in BUTTONS {
+                self.binding_ui(
+                    ui,
+                    format!("{button:?}"),
+                    Binding::Button {
+                        player2: false,
+                        button,
+                    },
+                );
+            }
+            self.binding_ui(ui, "turbo A".to_string(), Binding::TurboA);
+            self.binding_ui(ui, "turbo B".to_string(), Binding::TurboB);
+        });
+        ui.horizontal(|ui| {
+            ui.label("turbo presses per second");
+            ui.add(
+                egui::DragValue::new(&mut self.config.turbo.a_rate)
+                    .range(1..=30)
+                    .prefix("A: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.config.turbo.b_rate)
+                    .range(1..=30)
+                    .prefix("B: "),
+            );
+        });
+
+        if player2 {
+            ui.separator();
+            ui.strong("player 2");
+            egui::Grid::new("controls2").show(ui, |ui| {
+                for button in BUTTONS {
+                    self.binding_ui(
+                        ui,
+                        format!("{button:?}"),
+                        Binding::Button {
+                            player2: true,
+                            button,
+                        },
+                    );
+                }
+            });
+        }
+
+        // these only get edited in the config file
+        if !self.config.macros.is_empty() {
+            ui.separator();
+            ui.strong("macros");
+            for m in &self.config.macros {
+                ui.label(format!("{}: {}", m.name, m.keys.join(", ")));
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if let Some(path) = &self.path
+                && ui.button("save").clicked()
+            {
+                self.status = match self.config.save(path) {
+                    Ok(()) => format!("saved to {}", path.display()),
+                    Err(e) => format!("couldn't save: {e}"),
+                };
+            }
+            if ui.button("defaults").clicked() {
+                self.config = Config::default();
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+
+    fn keys_mut(&mut self, binding: Binding) -> &mut Vec<String> {
+        match binding {
+            Binding::Button {
+                player2: true,
+                button,
+            } => self.config.player2_keys.get_mut(button),
+            Binding::Button { button, .. } => self.config.keys.get_mut(button),
+            Binding::TurboA => &mut self.config.turbo.a,
+            Binding::TurboB => &mut self.config.turbo.b,
+        }
+    }
+
+    // click a key to unbind it, + waits for a new one
+    fn binding_ui(&mut self, ui: &mut egui::Ui, name: String, binding: Binding) {
+        ui.label(name);
+        ui.horizontal(|ui| {
+            let keys = self.keys_mut(binding);
+            keys.retain(|key| !ui.button(key).on_hover_text("unbind").clicked());
+
+            if self.capturing == Some(binding) {
+                ui.label("press a key (esc cancels)");
+            } else if ui.small_button("+").clicked() {
+                self.capturing = Some(binding);
+            }
+        });
+        ui.end_row();
+    }
+}
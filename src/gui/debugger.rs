@@ -0,0 +1,61 @@
+use eframe::egui;
+
+use crate::debugger::{self, format_reason, format_registers};
+use crate::gameboy::Gameboy;
+
+#[derive(Default)]
+pub struct DebuggerView {
+    input: String,
+    output: String,
+}
+
+impl DebuggerView {
+    pub fn ui(&mut self, ui: &mut egui::Ui, gb: &mut Gameboy) {
+        ui.horizontal(|ui| {
+            let dbg = &mut gb.debugger;
+            if dbg.is_paused() {
+                if ui.button("continue").clicked() {
+                    dbg.resume();
+                }
+            } else if ui.button("pause").clicked() {
+                dbg.pause();
+            }
+            if ui.button("step").clicked() {
+                dbg.step_into();
+            }
+            if ui.button("over").clicked() {
+                dbg.step_over(&gb.cpu);
+            }
+            if ui.button("out").clicked() {
+                dbg.step_out(&gb.cpu);
+            }
+            if ui.button("vblank").clicked() {
+                dbg.run_to_vblank();
+            }
+        });
+
+        ui.monospace(format_registers(gb.cpu.registers()));
+        ui.monospace(debugger::current_instruction(gb));
+        let status = match (gb.debugger.is_paused(), gb.debugger.reason) {
+            (true, Some(reason)) => format_reason(reason),
+            _ => "running".to_string(),
+        };
+        ui.label(status);
+        ui.separator();
+
+        // breakpoints and watchpoints are added through commands, same as the repl
+        let input = ui.text_edit_singleline(&mut self.input);
+        if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            self.output = match debugger::command(gb, &self.input) {
+                Ok(out) => out,
+                Err(e) => format!("error: {e}"),
+            };
+            self.input.clear();
+            input.request_focus();
+        }
+        if let Ok(list) = debugger::command(gb, "l") {
+            ui.monospace(list);
+        }
+        ui.monospace(&self.output);
+    }
+}
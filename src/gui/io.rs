@@ -0,0 +1,94 @@
+use eframe::egui;
+
+use crate::apu::{Control, Envelope, LengthDuty, Nr10, Nr30, Nr32, Nr43, Nr50, Nr51, Nr52};
+use crate::gameboy::Gameboy;
+use crate::joypad::P1;
+use crate::ppu::{Lcdc, Stat};
+use crate::serial::Sc;
+use crate::timer::Tac;
+
+type Decode = fn(u8) -> Vec<(&'static str, String)>;
+
+// reads each listed field of a bitfield register through its accessor
+macro_rules! decode {
+    ($ty:ty: $($field:ident),+) => {
+        |val| {
+            let reg = <$ty>::from(val);
+            vec![$((stringify!($field), reg.$field().to_string())),+]
+        }
+    };
+}
+
+#[rustfmt::skip]
+const REGISTERS: &[(&str, u16, Option<Decode>)] = &[
+    ("P1",   0xFF00, Some(decode!(P1: buttons, dpad, start_down, select_up, b_left, a_right))),
+    ("SB",   0xFF01, None),
+    ("SC",   0xFF02, Some(decode!(Sc: transfer_enable, clock_speed, clock_select))),
+    ("DIV",  0xFF04, None),
+    ("TIMA", 0xFF05, None),
+    ("TMA",  0xFF06, None),
+    ("TAC",  0xFF07, Some(decode!(Tac: enable, clock_select))),
+    ("IF",   0xFF0F, None),
+    ("NR10", 0xFF10, Some(decode!(Nr10: pace, decrease, step))),
+    ("NR11", 0xFF11, Some(decode!(LengthDuty: duty, initial_length))),
+    ("NR12", 0xFF12, Some(decode!(Envelope: initial_volume, increase, pace))),
+    ("NR13", 0xFF13, None),
+    ("NR14", 0xFF14, Some(decode!(Control: trigger, length_enable, period_high))),
+    ("NR21", 0xFF16, Some(decode!(LengthDuty: duty, initial_length))),
+    ("NR22", 0xFF17, Some(decode!(Envelope: initial_volume, increase, pace))),
+    ("NR23", 0xFF18, None),
+    ("NR24", 0xFF19, Some(decode!(Control: trigger, length_enable, period_high))),
+    ("NR30", 0xFF1A, Some(decode!(Nr30: dac_enable))),
+    ("NR31", 0xFF1B, None),
+    ("NR32", 0xFF1C, Some(decode!(Nr32: output_level))),
+    ("NR33", 0xFF1D, None),
+    ("NR34", 0xFF1E, Some(decode!(Control: trigger, length_enable, period_high))),
+    ("NR41", 0xFF20, Some(decode!(LengthDuty: duty, initial_length))),
+    ("NR42", 0xFF21, Some(decode!(Envelope: initial_volume, increase, pace))),
+    ("NR43", 0xFF22, Some(decode!(Nr43: clock_shift, short_lfsr, clock_divider))),
+    ("NR44", 0xFF23, Some(decode!(Control: trigger, length_enable, period_high))),
+    ("NR50", 0xFF24, Some(decode!(Nr50: vin_left, left_volume, vin_right, right_volume))),
+    ("NR51", 0xFF25, Some(decode!(Nr51:
+        ch4_left, ch3_left, ch2_left, ch1_left, ch4_right, ch3_right, ch2_right, ch1_right))),
+    ("NR52", 0xFF26, Some(decode!(Nr52: audio_on, ch4_on, ch3_on, ch2_on, ch1_on))),
+    ("LCDC", 0xFF40, Some(decode!(Lcdc:
+        lcd_ppu_enable, window_tilemap, window_enable, bg_window_tiles, bg_tilemap, obj_size,
+        obj_enable, bg_window_enable))),
+    ("STAT", 0xFF41, Some(decode!(Stat:
+        lyc_int_select, mode_2_int_select, mode_1_int_select, mode_0_int_select, lyc_eq_ly,
+        ppu_mode))),
+    ("SCY",  0xFF42, None),
+    ("SCX",  0xFF43, None),
+    ("LY",   0xFF44, None),
+    ("LYC",  0xFF45, None),
+    ("DMA",  0xFF46, None),
+    ("BGP",  0xFF47, None),
+    ("OBP0", 0xFF48, None),
+    ("OBP1", 0xFF49, None),
+    ("WY",   0xFF4A, None),
+    ("WX",   0xFF4B, None),
+    ("IE",   0xFFFF, None),
+];
+
+pub fn io_ui(ui: &mut egui::Ui, gb: &Gameboy) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for &(name, addr, decode) in REGISTERS {
+            let val = gb.cpu.bus.read_u8(addr);
+            let title = format!("{addr:04X} {name:<4} ${val:02X} %{val:08b}");
+            match decode {
+                Some(decode) => {
+                    egui::CollapsingHeader::new(egui::RichText::new(title).monospace())
+                        .id_salt(addr)
+                        .show(ui, |ui| {
+                            for (field, val) in decode(val) {
+                                ui.monospace(format!("{field}: {val}"));
+                            }
+                        });
+                }
+                None => {
+                    ui.monospace(title);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,143 @@
+use eframe::egui;
+
+use crate::gameboy::Gameboy;
+
+// hex editor over the whole address space. everything goes through the bus like a CPU access
+// would, except when a specific rom/sram bank is picked, then it reads straight out of the cart
+#[derive(Default)]
+pub struct MemoryView {
+    rom_bank: Option<usize>, // None is whatever's mapped right now
+    ram_bank: Option<usize>,
+    goto: String,
+    scroll_to: Option<u16>,
+    editing: Option<(u16, String)>,
+}
+
+impl MemoryView {
+    fn peek(&self, gb: &Gameboy, addr: u16) -> Option<u8> {
+        let cart = &gb.cpu.bus.cart;
+        match addr {
+            0x4000..=0x7FFF if let Some(bank) = self.rom_bank => cart
+                .rom()
+                .get((bank << 14) | (addr as usize & 0x3FFF))
+                .copied(),
+            0xA000..=0xBFFF if let Some(bank) = self.ram_bank => cart
+                .ram()
+                .get((bank << 13) | (addr as usize & 0x1FFF))
+                .copied(),
+            // reading here complains on every read, and there's nothing useful in it anyway
+            0xFEA0..=0xFEFF => None,
+            _ => Some(gb.cpu.bus.read_u8(addr)),
+        }
+    }
+
+    fn poke(&self, gb: &mut Gameboy, addr: u16, val: u8) {
+        match addr {
+            // writes here would just poke the mbc registers
+            0x0000..=0x7FFF => (),
+            0xA000..=0xBFFF if let Some(bank) = self.ram_bank => {
+                let ram = gb.cpu.bus.cart.ram_mut();
+                if let Some(byte) = ram.get_mut((bank << 13) | (addr as usize & 0x1FFF)) {
+                    *byte = val;
+                }
+            }
+            0xFEA0..=0xFEFF => (),
+            _ => gb.cpu.bus.write_u8(addr, val),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, gb: &mut Gameboy) {
+        let rom_banks = gb.cpu.bus.cart.rom().len().div_ceil(0x4000);
+        let ram_banks = gb.cpu.bus.cart.ram().len().div_ceil(0x2000);
+
+        ui.horizontal(|ui| {
+            ui.label("go to");
+            let goto = ui.add(egui::TextEdit::singleline(&mut self.goto).desired_width(40.0));
+            if goto.lost_focus()
+                && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                && let Ok(addr) = u16::from_str_radix(self.goto.trim_start_matches('$'), 16)
+            {
+                self.scroll_to = Some(addr);
+            }
+            bank_select(ui, "rom bank", &mut self.rom_bank, 1..rom_banks);
+            if ram_banks > 0 {
+                bank_select(ui, "sram bank", &mut self.ram_bank, 0..ram_banks);
+            }
+        });
+        ui.separator();
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let mut scroll = egui::ScrollArea::vertical().auto_shrink([false, true]);
+        if let Some(addr) = self.scroll_to.take() {
+            let spacing = ui.spacing().item_spacing.y;
+            scroll = scroll.vertical_scroll_offset((addr / 16) as f32 * (row_height + spacing));
+        }
+        scroll.show_rows(ui, row_height, 0x1000, |ui, rows| {
+            for row in rows {
+                let base = (row * 16) as u16;
+                ui.horizontal(|ui| {
+                    ui.monospace(format!("{base:04X}"));
+                    // the last row ends right at $FFFF, base + 16 would overflow
+                    for addr in (0..16).map(|i| base.wrapping_add(i)) {
+                        self.byte_ui(ui, gb, addr);
+                    }
+                });
+            }
+        });
+    }
+
+    // click a byte to edit it, enter writes it
+    fn byte_ui(&mut self, ui: &mut egui::Ui, gb: &mut Gameboy, addr: u16) {
+        if let Some((edit_addr, text)) = &mut self.editing
+            && *edit_addr == addr
+        {
+            let edit = ui.add(
+                egui::TextEdit::singleline(text)
+                    .font(egui::TextStyle::Monospace)
+                    .char_limit(2)
+                    .desired_width(16.0),
+            );
+            edit.request_focus();
+            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                if let Ok(val) = u8::from_str_radix(text, 16) {
+                    self.poke(gb, addr, val);
+                }
+                self.editing = None;
+            } else if edit.lost_focus() {
+                self.editing = None;
+            }
+            return;
+        }
+
+        let text = match self.peek(gb, addr) {
+            Some(val) => format!("{val:02X}"),
+            None => "--".to_string(),
+        };
+        let label = ui.add(
+            egui::Label::new(egui::RichText::new(text).monospace()).sense(egui::Sense::click()),
+        );
+        if label.clicked() {
+            self.editing = Some((addr, String::new()));
+        }
+    }
+}
+
+fn bank_select(
+    ui: &mut egui::Ui,
+    label: &str,
+    bank: &mut Option<usize>,
+    banks: std::ops::Range<usize>,
+) {
+    let text = match bank {
+        Some(bank) => format!("{bank:02X}"),
+        None => "mapped".to_string(),
+    };
+    egui::ComboBox::from_label(label)
+        .selected_text(text)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(bank, None, "mapped");
+            for b in banks {
+                ui.selectable_value(bank, Some(b), format!("{b:02X}"));
+            }
+        });
+}
@@ -0,0 +1,333 @@
+use eframe::egui::TextureOptions;
+use eframe::egui::{self, ColorImage};
+use eframe::egui::{Color32, TextureHandle, widgets::Image};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::Gameboy;
+use crate::config::Config;
+use crate::cpu::CpuState;
+use crate::gameboy::BUTTONS;
+use crate::input::Held;
+use crate::movie::Movie;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use controls::ControlsView;
+use debugger::DebuggerView;
+use memory::MemoryView;
+use vram::{MapView, TileView};
+
+mod controls;
+mod debugger;
+mod io;
+mod memory;
+mod vram;
+
+pub const GUI_SCALE: usize = 4;
+pub const FPS: f64 = 60.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Debugger,
+    Memory,
+    IoRegisters,
+    Tiles,
+    TileMap,
+    Oam,
+    Controls,
+}
+
+const TOOLS: [Tool; 7] = [
+    Tool::Debugger,
+    Tool::Memory,
+    Tool::IoRegisters,
+    Tool::Tiles,
+    Tool::TileMap,
+    Tool::Oam,
+    Tool::Controls,
+];
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Debugger => "debugger",
+            Tool::Memory => "memory",
+            Tool::IoRegisters => "io registers",
+            Tool::Tiles => "tiles",
+            Tool::TileMap => "tilemap",
+            Tool::Oam => "oam",
+            Tool::Controls => "controls",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct ToolState {
+    open: bool,
+    docked: bool,
+}
+
+pub struct Gui {
+    gb: Gameboy,
+    screen: TextureHandle,
+    player2: Option<(Gameboy, TextureHandle)>, // a second gameboy on a LocalLink, drawn to the right
+    movie: Option<Movie>,
+    last_frame: Instant,
+    show_tools: bool,
+    tools: [ToolState; TOOLS.len()],
+    debugger: DebuggerView,
+    memory: MemoryView,
+    tiles: TileView,
+    map: MapView,
+    controls: ControlsView,
+    highlight: Option<usize>, // object to outline on the screen
+}
+
+impl Gui {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        gb: Gameboy,
+        player2: Option<Gameboy>,
+        movie: Option<Movie>,
+        config: Config,
+        config_path: Option<PathBuf>,
+    ) -> Self {
+        let load_screen = |name| {
+            cc.egui_ctx.load_texture(
+                name,
+                egui::ColorImage::new([SCREEN_WIDTH, SCREEN_HEIGHT], Color32::WHITE),
+                TextureOptions::NEAREST,
+            )
+        };
+        Self {
+            gb,
+            screen: load_screen("screen"),
+            player2: player2.map(|gb| (gb, load_screen("screen2"))),
+            movie,
+            last_frame: Instant::now(),
+            show_tools: false,
+            tools: TOOLS.map(|tool| ToolState {
+                open: tool == Tool::Debugger,
+                docked: false,
+            }),
+            debugger: DebuggerView::default(),
+            memory: MemoryView::default(),
+            tiles: TileView::default(),
+            map: MapView::default(),
+            controls: ControlsView::new(config, config_path),
+            highlight: None,
+        }
+    }
+
+    fn update_screen(screen: &mut TextureHandle, gb: &Gameboy) {
+        let mut pixels = vec![Color32::WHITE; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let frame = gb.get_frame();
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                pixels[y * SCREEN_WIDTH + x] = frame[y][x].into();
+            }
+        }
+
+        screen.set(
+            ColorImage {
+                size: [SCREEN_WIDTH, SCREEN_HEIGHT],
+                pixels,
+            },
+            TextureOptions::NEAREST,
+        );
+    }
+
+    // the keyboard only says what's held, turbo and macros happen in the input layer
+    fn update_input(&mut self, ctx: &egui::Context) {
+        let config = &self.controls.config;
+        let mut held = Held::default();
+        let mut held2 = 0;
+        if !ctx.wants_keyboard_input() && !self.controls.capturing() {
+            ctx.input(|i| {
+                for button in BUTTONS {
+                    if keys_down(i, config.keys.get(button)) {
+                        held.buttons |= button.mask();
+                    }
+                    if keys_down(i, config.player2_keys.get(button)) {
+                        held2 |= button.mask();
+                    }
+                }
+                held.turbo_a = keys_down(i, &config.turbo.a);
+                held.turbo_b = keys_down(i, &config.turbo.b);
+                held.macros = config
+                    .macros
+                    .iter()
+                    .map(|m| keys_down(i, &m.keys))
+                    .collect();
+                if i.key_pressed(egui::Key::F12) {
+                    self.show_tools = !self.show_tools;
+                }
+            });
+        }
+        self.gb.held = held;
+        // the controls window might have changed the turbo rates
+        let input = &mut self.gb.input;
+        if input.turbo != config.turbo || input.macros != config.macros {
+            input.turbo = config.turbo.clone();
+            input.macros = config.macros.clone();
+        }
+        if let Some((gb, _)) = &mut self.player2 {
+            gb.held = Held {
+                buttons: held2,
+                ..Default::default()
+            };
+        }
+    }
+
+    fn tool_ui(&mut self, tool: Tool, ui: &mut egui::Ui) {
+        match tool {
+            Tool::Debugger => self.debugger.ui(ui, &mut self.gb),
+            Tool::Memory => self.memory.ui(ui, &mut self.gb),
+            Tool::IoRegisters => io::io_ui(ui, &self.gb),
+            Tool::Tiles => self.tiles.ui(ui, &self.gb),
+            Tool::TileMap => self.map.ui(ui, &self.gb),
+            Tool::Oam => self.highlight = vram::oam_ui(ui, &self.gb),
+            Tool::Controls => self.controls.ui(ui, self.player2.is_some()),
+        }
+    }
+
+    // open tools are either floating windows or docked in a panel on the right
+    fn show_tools(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("tools").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (tool, state) in TOOLS.iter().zip(&mut self.tools) {
+                    ui.checkbox(&mut state.open, tool.name());
+                }
+            });
+        });
+
+        if self.tools.iter().any(|t| t.open && t.docked) {
+            egui::SidePanel::right("dock")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for (i, &tool) in TOOLS.iter().enumerate() {
+                            if !self.tools[i].open || !self.tools[i].docked {
+                                continue;
+                            }
+                            egui::CollapsingHeader::new(tool.name())
+                                .default_open(true)
+                                .show(ui, |ui| {
+                                    if ui.small_button("undock").clicked() {
+                                        self.tools[i].docked = false;
+                                    }
+                                    self.tool_ui(tool, ui);
+                                });
+                        }
+                    });
+                });
+        }
+
+        for (i, &tool) in TOOLS.iter().enumerate() {
+            if !self.tools[i].open || self.tools[i].docked {
+                continue;
+            }
+            let mut open = true;
+            egui::Window::new(tool.name())
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    if ui.small_button("dock").clicked() {
+                        self.tools[i].docked = true;
+                    }
+                    self.tool_ui(tool, ui);
+                });
+            self.tools[i].open &= open;
+        }
+    }
+}
+
+// any of the keys bound to a button counts
+fn keys_down(input: &egui::InputState, keys: &[String]) -> bool {
+    keys.iter()
+        .filter_map(|name| egui::Key::from_name(name))
+        .any(|key| input.key_down(key))
+}
+
+fn locked_text(ui: &egui::Ui, rect: egui::Rect, pc: u16) {
+    ui.painter().text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        format!("CPU locked at ${pc:04X}"),
+        egui::FontId::proportional(24.0),
+        Color32::RED,
+    );
+}
+
+impl eframe::App for Gui {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let frame_goal = Duration::from_secs_f64(1.0 / FPS);
+        let now = Instant::now();
+        //println!("fps: {}", 1.0 / (now - self.last_frame).as_secs_f64());
+        self.last_frame = now;
+
+        self.update_input(ctx);
+
+        match &mut self.player2 {
+            Some((gb2, screen2)) => {
+                self.gb.run_frame_linked(gb2);
+                Self::update_screen(screen2, gb2);
+            }
+            None => {
+                if let Some(movie) = &mut self.movie {
+                    movie.before_frame(&mut self.gb);
+                }
+                if self.gb.run_frame()
+                    && let Some(movie) = &mut self.movie
+                {
+                    movie.after_frame(&self.gb);
+                }
+            }
+        }
+        Self::update_screen(&mut self.screen, &self.gb);
+
+        // panels have to go in before the central one so the screen gets what's left
+        self.highlight = None;
+        if self.show_tools {
+            self.show_tools(ctx);
+        }
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show(ctx, |ui| {
+                let mut rect = ui.max_rect();
+                if let Some((gb2, screen2)) = &self.player2 {
+                    let (left, right) = rect.split_left_right_at_fraction(0.5);
+                    rect = left;
+                    Image::new(screen2).paint_at(ui, right);
+                    if let CpuState::Locked(pc) = gb2.cpu_state() {
+                        locked_text(ui, right, pc);
+                    }
+                }
+                let image = Image::new(&self.screen);
+                image.paint_at(ui, rect);
+
+                if let Some(obj) = self.highlight
+                    && let Some(obj_rect) = vram::object_rect(&self.gb, obj)
+                {
+                    let scale = rect.width() / SCREEN_WIDTH as f32;
+                    let obj_rect = egui::Rect::from_min_max(
+                        rect.min + obj_rect.min.to_vec2() * scale,
+                        rect.min + obj_rect.max.to_vec2() * scale,
+                    );
+                    ui.painter()
+                        .rect_stroke(obj_rect, 0.0, egui::Stroke::new(2.0, Color32::RED));
+                }
+
+                if let CpuState::Locked(pc) = self.gb.cpu_state() {
+                    locked_text(ui, rect, pc);
+                }
+            });
+
+        let frame_time = now.elapsed();
+        if frame_time > frame_goal {
+            ctx.request_repaint();
+        } else {
+            ctx.request_repaint_after(frame_goal - frame_time);
+        }
+        // ctx.request_repaint_after(Duration::from_millis(17));
+    }
+}
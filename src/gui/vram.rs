@@ -0,0 +1,237 @@
+use eframe::egui::{
+    self, Color32, ColorImage, Rect, Stroke, TextureHandle, TextureOptions, pos2, vec2,
+};
+
+use crate::gameboy::Gameboy;
+use crate::ppu::{Color, Lcdc, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const TILES_PER_ROW: usize = 16;
+const VIEW_SCALE: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Palette {
+    Identity, // raw color indexes, ignoring the game's palettes
+    Bgp,
+    Obp0,
+    Obp1,
+}
+
+impl Palette {
+    fn name(self) -> &'static str {
+        match self {
+            Palette::Identity => "none",
+            Palette::Bgp => "BGP",
+            Palette::Obp0 => "OBP0",
+            Palette::Obp1 => "OBP1",
+        }
+    }
+
+    fn value(self, gb: &Gameboy) -> u8 {
+        match self {
+            Palette::Identity => 0b11100100,
+            Palette::Bgp => gb.cpu.bus.read_u8(0xFF47),
+            Palette::Obp0 => gb.cpu.bus.read_u8(0xFF48),
+            Palette::Obp1 => gb.cpu.bus.read_u8(0xFF49),
+        }
+    }
+}
+
+// draws an 8x8 tile into a bigger image at tile position tx, ty
+fn blit_tile(image: &mut ColorImage, tile: [[Color; 8]; 8], tx: usize, ty: usize) {
+    let width = image.size[0];
+    for (y, row) in tile.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            image.pixels[(ty * 8 + y) * width + tx * 8 + x] = (*color).into();
+        }
+    }
+}
+
+// keeps one texture per view around and refills it every frame
+fn show_image(
+    ui: &mut egui::Ui,
+    texture: &mut Option<TextureHandle>,
+    name: &str,
+    image: ColorImage,
+) -> egui::Response {
+    let size = vec2(image.size[0] as f32, image.size[1] as f32) * VIEW_SCALE;
+    let texture = match texture {
+        Some(texture) => {
+            texture.set(image, TextureOptions::NEAREST);
+            texture
+        }
+        None => texture.insert(ui.ctx().load_texture(name, image, TextureOptions::NEAREST)),
+    };
+    ui.add(egui::Image::new((texture.id(), size)).sense(egui::Sense::hover()))
+}
+
+pub struct TileView {
+    palette: Palette,
+    texture: Option<TextureHandle>,
+}
+
+impl Default for TileView {
+    fn default() -> Self {
+        Self {
+            palette: Palette::Bgp,
+            texture: None,
+        }
+    }
+}
+
+impl TileView {
+    pub fn ui(&mut self, ui: &mut egui::Ui, gb: &Gameboy) {
+        egui::ComboBox::from_label("palette")
+            .selected_text(self.palette.name())
+            .show_ui(ui, |ui| {
+                for palette in [
+                    Palette::Identity,
+                    Palette::Bgp,
+                    Palette::Obp0,
+                    Palette::Obp1,
+                ] {
+                    ui.selectable_value(&mut self.palette, palette, palette.name());
+                }
+            });
+
+        let palette = self.palette.value(gb);
+        let rows = 384 / TILES_PER_ROW;
+        let mut image = ColorImage::new([TILES_PER_ROW * 8, rows * 8], Color32::WHITE);
+        for i in 0..384 {
+            let tile = gb.cpu.bus.ppu.tile_colors(i, palette);
+            blit_tile(&mut image, tile, i % TILES_PER_ROW, i / TILES_PER_ROW);
+        }
+
+        let response = show_image(ui, &mut self.texture, "tiles", image);
+        if let Some(pos) = response.hover_pos() {
+            let pos = (pos - response.rect.min) / (8.0 * VIEW_SCALE);
+            let index = pos.y as usize * TILES_PER_ROW + pos.x as usize;
+            if index < 384 {
+                response.on_hover_text(format!("tile {index} at ${:04X}", 0x8000 + index * 16));
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MapView {
+    window: bool, // show the map the window uses instead of the background one
+    texture: Option<TextureHandle>,
+}
+
+impl MapView {
+    pub fn ui(&mut self, ui: &mut egui::Ui, gb: &Gameboy) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.window, false, "background");
+            ui.selectable_value(&mut self.window, true, "window");
+        });
+
+        let lcdc = Lcdc::from(gb.cpu.bus.read_u8(0xFF40));
+        let upper_map = match self.window {
+            true => lcdc.window_tilemap(),
+            false => lcdc.bg_tilemap(),
+        };
+        ui.label(format!(
+            "map at ${:04X}",
+            if upper_map { 0x9C00 } else { 0x9800 }
+        ));
+
+        let ppu = &gb.cpu.bus.ppu;
+        let palette = gb.cpu.bus.read_u8(0xFF47);
+        let mut image = ColorImage::new([256, 256], Color32::WHITE);
+        for y in 0..32 {
+            for x in 0..32 {
+                let tile = ppu.tile_colors(ppu.map_tile(upper_map, x, y), palette);
+                blit_tile(&mut image, tile, x as usize, y as usize);
+            }
+        }
+        let response = show_image(ui, &mut self.texture, "tilemap", image);
+
+        // the visible part of the map, the background wraps around so this can come in up to
+        // four pieces
+        let stroke = Stroke::new(2.0, Color32::RED);
+        let painter = ui.painter_at(response.rect);
+        let to_screen = |x: f32, y: f32| response.rect.min + vec2(x, y) * VIEW_SCALE;
+        if self.window {
+            let wx = gb.cpu.bus.read_u8(0xFF4B) as f32 - 7.0;
+            let wy = gb.cpu.bus.read_u8(0xFF4A) as f32;
+            let w = SCREEN_WIDTH as f32 - wx.max(0.0);
+            let h = SCREEN_HEIGHT as f32 - wy;
+            if w > 0.0 && h > 0.0 {
+                painter.rect_stroke(
+                    Rect::from_min_max(to_screen(0.0, 0.0), to_screen(w, h)),
+                    0.0,
+                    stroke,
+                );
+            }
+        } else {
+            let scx = gb.cpu.bus.read_u8(0xFF43) as f32;
+            let scy = gb.cpu.bus.read_u8(0xFF42) as f32;
+            for dx in [0.0, -256.0] {
+                for dy in [0.0, -256.0] {
+                    let min = pos2(scx + dx, scy + dy);
+                    let max = min + vec2(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+                    if max.x > 0.0 && max.y > 0.0 {
+                        painter.rect_stroke(
+                            Rect::from_min_max(to_screen(min.x, min.y), to_screen(max.x, max.y)),
+                            0.0,
+                            stroke,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// returns the object the mouse is over, so it can get highlighted on the screen
+pub fn oam_ui(ui: &mut egui::Ui, gb: &Gameboy) -> Option<usize> {
+    let mut hovered = None;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::Grid::new("oam").striped(true).show(ui, |ui| {
+            for title in ["#", "x", "y", "tile", "flags"] {
+                ui.strong(title);
+            }
+            ui.end_row();
+
+            for (i, obj) in gb.cpu.bus.ppu.objects().iter().enumerate() {
+                let flags = obj.flags;
+                let cells = [
+                    format!("{i}"),
+                    format!("{}", obj.x),
+                    format!("{}", obj.y),
+                    format!("${:02X}", obj.tile),
+                    format!(
+                        "{} {} {} OBP{}",
+                        if flags.priority() { "behind" } else { "front" },
+                        if flags.x_flip() { "xflip" } else { "-" },
+                        if flags.y_flip() { "yflip" } else { "-" },
+                        flags.dmg_palette() as u8,
+                    ),
+                ];
+                for cell in cells {
+                    if ui.monospace(cell).hovered() {
+                        hovered = Some(i);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    });
+    hovered
+}
+
+// outline of an object on the screen, None if it's entirely off screen
+pub fn object_rect(gb: &Gameboy, index: usize) -> Option<Rect> {
+    let obj = gb.cpu.bus.ppu.objects()[index];
+    let height = match Lcdc::from(gb.cpu.bus.read_u8(0xFF40)).obj_size() {
+        true => 16.0,
+        false => 8.0,
+    };
+    let min = pos2(obj.x as f32 - 8.0, obj.y as f32 - 16.0);
+    let rect = Rect::from_min_size(min, vec2(8.0, height));
+    let screen = Rect::from_min_size(
+        pos2(0.0, 0.0),
+        vec2(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32),
+    );
+    rect.intersects(screen).then_some(rect)
+}
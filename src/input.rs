@@ -0,0 +1,144 @@
+use crate::config::{Config, Macro, Turbo};
+use crate::gameboy::GbBtn;
+
+// what the frontend has held down this frame, before turbo and macros get applied
+#[derive(Debug, Default, Clone)]
+pub struct Held {
+    pub buttons: u8, // same bits as Gameboy::buttons
+    pub turbo_a: bool,
+    pub turbo_b: bool,
+    pub macros: Vec<bool>, // one per macro in the config
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Running {
+    index: usize,
+    step: usize,
+    frames_left: u32,
+}
+
+// sits between the keyboard (or whatever else) and the gameboy's buttons, Gameboy::run_frame
+// runs it as each frame starts. everything is counted in emulated frames, so turbo and macros
+// come out the same no matter how fast the frontend runs or how long the debugger has it paused,
+// and a recorded movie only has to store the buttons that came out of here
+#[derive(Debug, Default)]
+pub struct InputLayer {
+    pub turbo: Turbo,
+    pub macros: Vec<Macro>,
+    frame: u64,
+    running: Option<Running>,
+    was_held: Vec<bool>, // macros start on the press, not while the key's held
+}
+
+impl InputLayer {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            turbo: config.turbo.clone(),
+            macros: config.macros.clone(),
+            ..Default::default()
+        }
+    }
+
+    // the buttons for the next frame, has to be called exactly once per emulated frame
+    pub fn apply(&mut self, held: &Held) -> u8 {
+        let mut buttons = held.buttons;
+        if held.turbo_a && self.turbo_on(self.turbo.a_rate) {
+            buttons |= GbBtn::A.mask();
+        }
+        if held.turbo_b && self.turbo_on(self.turbo.b_rate) {
+            buttons |= GbBtn::B.mask();
+        }
+
+        if self.running.is_none()
+            && let Some(index) = (0..held.macros.len())
+                .find(|&i| held.macros[i] && !self.was_held.get(i).copied().unwrap_or(false))
+        {
+            self.running = Some(Running {
+                index,
+                step: 0,
+                frames_left: 0,
+            });
+        }
+        self.was_held = held.macros.clone();
+        buttons |= self.macro_buttons();
+
+        self.frame += 1;
+        buttons
+    }
+
+    // pressed for the first half of every period
+    fn turbo_on(&self, rate: u32) -> bool {
+        (self.frame * rate.clamp(1, 30) as u64 * 2 / 60).is_multiple_of(2)
+    }
+
+    fn macro_buttons(&mut self) -> u8 {
+        let Some(running) = &mut self.running else {
+            return 0;
+        };
+        let Some(steps) = self.macros.get(running.index).map(|m| &m.steps) else {
+            self.running = None;
+            return 0;
+        };
+        // moves on to the next step once the current one's frames are used up
+        while running.frames_left == 0 {
+            if running.step >= steps.len() {
+                self.running = None;
+                return 0;
+            }
+            running.frames_left = steps[running.step].frames;
+            running.step += 1;
+        }
+        running.frames_left -= 1;
+        steps[running.step - 1]
+            .buttons
+            .iter()
+            .fold(0, |mask, button| mask | button.mask())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::test_gameboy;
+
+    const A: u8 = 1 << 4;
+
+    #[test]
+    fn turbo_counts_emulated_frames() {
+        let mut gb = test_gameboy(0x00, &[0x18, 0xFE]);
+        gb.input = InputLayer::new(&Config::default());
+        gb.held.turbo_a = true;
+
+        let mut pressed = vec![];
+        for frame in 0..12 {
+            // the debugger holding things up partway through shouldn't move turbo along
+            if frame == 4 {
+                gb.debugger.step_into();
+                for _ in 0..5 {
+                    gb.run_frame();
+                }
+                gb.debugger.resume();
+            }
+            while !gb.run_frame() {}
+            pressed.push(gb.buttons() & A != 0);
+        }
+        // 10 presses a second is 3 frames down, 3 frames up
+        let on = [true, true, true, false, false, false];
+        assert_eq!(pressed, [on, on].concat());
+    }
+
+    #[test]
+    fn macro_runs_once_per_press() {
+        let mut gb = test_gameboy(0x00, &[0x18, 0xFE]);
+        gb.input = InputLayer::new(&Config::default());
+        gb.held.macros = vec![true];
+
+        let mut frames = vec![];
+        for _ in 0..15 {
+            gb.run_frame();
+            frames.push(gb.buttons());
+        }
+        // A+B+select+start for 10 frames, then nothing even though the key's still held
+        assert_eq!(frames, [vec![0xF0; 10], vec![0x00; 5]].concat());
+    }
+}
@@ -43,38 +43,44 @@ impl Joypad {
         }
     }
 
+    // only the select bits can be written, the lines are read only
     pub fn write_u8(&mut self, val: u8) {
-        self.p1 = val.into();
+        self.p1 = ((val & 0x30) | (u8::from(self.p1) & 0x0F)).into();
     }
 
     pub fn read_u8(&self) -> u8 {
         self.p1.into()
     }
 
+    // true when any of the selected lines is being pulled low, which is what wakes up STOP
+    pub fn lines_low(&self) -> bool {
+        u8::from(self.p1) & 0x0F != 0x0F
+    }
+
     pub fn tick(&mut self) {
+        // a pressed button pulls its line low when its group is selected, with both groups
+        // selected either button on a line does it
+        let mut lines = 0x0F;
         if !self.p1.buttons() {
-            if self.start || self.select || self.b || self.a {
-                self.joypad_int = true;
-            }
-            self.p1.set_start_down(!self.start);
-            self.p1.set_select_up(!self.select);
-            self.p1.set_b_left(!self.b);
-            self.p1.set_a_right(!self.a);
-        } else if !self.p1.dpad() {
-            if self.down || self.up || self.left || self.right {
-                self.joypad_int = true;
-            }
-            self.p1.set_start_down(!self.down);
-            self.p1.set_select_up(!self.up);
-            self.p1.set_b_left(!self.left);
-            self.p1.set_a_right(!self.right);
-        } else {
-            self.p1.set_start_down(true);
-            self.p1.set_select_up(true);
-            self.p1.set_b_left(true);
-            self.p1.set_a_right(true);
+            lines &= !button_bits(self.start, self.select, self.b, self.a);
+        }
+        if !self.p1.dpad() {
+            lines &= !button_bits(self.down, self.up, self.left, self.right);
+        }
+
+        // the interrupt is for a line going from high to low, holding a button doesn't keep
+        // firing it
+        let old = u8::from(self.p1) & 0x0F;
+        if old & !lines != 0 {
+            self.joypad_int = true;
         }
+
         // keep upper bits high
-        self.p1.set_upper_bits(0x03);
+        self.p1 = ((u8::from(self.p1) & 0x30) | 0xC0 | lines).into();
     }
 }
+
+// bits for P13 to P10
+fn button_bits(p13: bool, p12: bool, p11: bool, p10: bool) -> u8 {
+    (p13 as u8) << 3 | (p12 as u8) << 2 | (p11 as u8) << 1 | p10 as u8
+}
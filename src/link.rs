@@ -0,0 +1,410 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::serial::SerialDevice;
+
+const HELLO: &[u8; 8] = b"GBLINK01";
+
+// how long a byte from the other side waits for us to be ready before it gets bounced back as
+// if nobody was listening. games poke SC a little after the other side starts clocking all the
+// time, and over a network "a little" can be a lot longer than on a real cable
+const HOLD_TIMEOUT: Duration = Duration::from_millis(250);
+
+// every message is two bytes, what kind it is and the byte being shifted over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Msg {
+    Clock(u8), // the sender is driving the clock for this byte
+    Reply(u8), // the sender's byte for a Clock it got
+}
+
+impl Msg {
+    fn encode(self) -> [u8; 2] {
+        match self {
+            Msg::Clock(val) => [b'C', val],
+            Msg::Reply(val) => [b'R', val],
+        }
+    }
+
+    fn decode(bytes: [u8; 2]) -> Option<Self> {
+        match bytes[0] {
+            b'C' => Some(Msg::Clock(bytes[1])),
+            b'R' => Some(Msg::Reply(bytes[1])),
+            _ => None,
+        }
+    }
+}
+
+// a link cable to another emulator over tcp or a unix socket. which side is the master is
+// decided per byte by whoever sets SC to the internal clock, same as a real cable. the two ends
+// don't run in sync at all, instead a master's transfer just doesn't finish until the reply
+// makes it back, which games are fine with since they wait for the serial interrupt anyway
+pub struct LinkCable {
+    writer: Box<dyn Write + Send>,
+    incoming: Receiver<Msg>,
+    held: Option<(u8, Instant)>, // a byte the other side clocked in before we were ready for it
+    reply: Option<u8>,
+    connected: bool,
+}
+
+impl fmt::Debug for LinkCable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkCable")
+            .field("held", &self.held)
+            .field("reply", &self.reply)
+            .field("connected", &self.connected)
+            .finish()
+    }
+}
+
+impl LinkCable {
+    // addresses are host:port for tcp or unix:path for a unix socket
+    pub fn listen(addr: &str) -> io::Result<Self> {
+        println!("waiting for link cable on {addr}");
+        #[cfg(unix)]
+        if let Some(path) = addr.strip_prefix("unix:") {
+            // a socket left over from last time would make bind fail
+            let _ = std::fs::remove_file(path);
+            let (stream, _) = std::os::unix::net::UnixListener::bind(path)?.accept()?;
+            // unix peers don't have an address worth printing
+            println!("link cable connected on {path}");
+            return Self::new(stream.try_clone()?, stream);
+        }
+        let (stream, peer) = TcpListener::bind(addr)?.accept()?;
+        println!("link cable connected from {peer}");
+        stream.set_nodelay(true)?;
+        Self::new(stream.try_clone()?, stream)
+    }
+
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        #[cfg(unix)]
+        if let Some(path) = addr.strip_prefix("unix:") {
+            let stream = std::os::unix::net::UnixStream::connect(path)?;
+            return Self::new(stream.try_clone()?, stream);
+        }
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Self::new(stream.try_clone()?, stream)
+    }
+
+    fn new(
+        mut reader: impl Read + Send + 'static,
+        mut writer: impl Write + Send + 'static,
+    ) -> io::Result<Self> {
+        writer.write_all(HELLO)?;
+        let mut hello = [0; 8];
+        reader.read_exact(&mut hello)?;
+        if &hello != HELLO {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the other end isn't a link cable",
+            ));
+        }
+
+        // reads block, so they get their own thread and the emulator just checks the channel
+        let (tx, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let mut bytes = [0; 2];
+            while reader.read_exact(&mut bytes).is_ok() {
+                let Some(msg) = Msg::decode(bytes) else {
+                    break;
+                };
+                if tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            writer: Box::new(writer),
+            incoming,
+            held: None,
+            reply: None,
+            connected: true,
+        })
+    }
+
+    fn write(&mut self, msg: Msg) {
+        if !self.connected {
+            return;
+        }
+        if self.writer.write_all(&msg.encode()).is_err() {
+            self.disconnect();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if self.connected {
+            println!("WARNING: link cable disconnected");
+            self.connected = false;
+        }
+    }
+
+    fn next(&mut self) -> Option<Msg> {
+        match self.incoming.try_recv() {
+            Ok(msg) => Some(msg),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.disconnect();
+                None
+            }
+        }
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn send(&mut self, out: u8) {
+        // the other side already started clocking a byte at us. it'll treat our Clock as the
+        // reply, and we take the byte it sent, so both ends think they were the master
+        self.reply = self.held.take().map(|(val, _)| val);
+        self.write(Msg::Clock(out));
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        if let Some(val) = self.reply.take() {
+            return Some(val);
+        }
+        // a Clock while we're waiting means both sides started at once, same deal as in send
+        match self.next() {
+            Some(Msg::Clock(val) | Msg::Reply(val)) => Some(val),
+            None if !self.connected => Some(0xFF),
+            None => None,
+        }
+    }
+
+    fn poll(&mut self, sb: u8, ready: bool) -> Option<u8> {
+        if self.held.is_none() {
+            match self.next() {
+                Some(Msg::Clock(val)) => self.held = Some((val, Instant::now())),
+                // a reply for a transfer that got cancelled by rewriting SC
+                Some(Msg::Reply(_)) | None => return None,
+            }
+        }
+
+        let (val, since) = self.held?;
+        if ready {
+            self.held = None;
+            self.write(Msg::Reply(sb));
+            Some(val)
+        } else if since.elapsed() > HOLD_TIMEOUT {
+            self.held = None;
+            self.write(Msg::Reply(0xFF));
+            None
+        } else {
+            None
+        }
+    }
+}
+
+// both ends of an in-memory cable, indexed by which side is receiving
+#[derive(Debug, Default)]
+struct Wire {
+    clocked: [Option<u8>; 2], // what the master is shifting over while its transfer runs
+    replies: [Option<u8>; 2], // what the master gets back, set once the other side is ready
+    done: [Option<u8>; 2],    // the slave's byte, handed over when the master's transfer ends
+}
+
+// a link cable between two gameboys in the same process. there's no lag to deal with here, so
+// unlike LinkCable this behaves like the real thing: the other side has to be ready before the
+// master's 8 bits are done, otherwise the master just gets 1s. both sides finish together when
+// the master's last bit goes out, or as close to together as the two gameboys get stepped (an
+// instruction apart at most with Gameboy::run_frame_linked)
+#[derive(Debug)]
+pub struct LocalLink {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+impl LocalLink {
+    pub fn pair() -> (Self, Self) {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        (
+            Self {
+                wire: wire.clone(),
+                side: 0,
+            },
+            Self { wire, side: 1 },
+        )
+    }
+}
+
+impl SerialDevice for LocalLink {
+    fn send(&mut self, out: u8) {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        // both sides clocking at once, they each end up with the other's byte
+        if let Some(val) = wire.clocked[self.side].take() {
+            wire.replies[self.side] = Some(val);
+            wire.replies[other] = Some(out);
+        } else {
+            wire.clocked[other] = Some(out);
+        }
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        let reply = wire.replies[self.side].take();
+        // the transfer's over, the slave gets our byte only if it was there for it
+        if let Some(out) = wire.clocked[other].take()
+            && reply.is_some()
+        {
+            wire.done[other] = Some(out);
+        }
+        Some(reply.unwrap_or(0xFF))
+    }
+
+    fn poll(&mut self, sb: u8, ready: bool) -> Option<u8> {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        if let Some(val) = wire.done[self.side].take() {
+            return Some(val);
+        }
+        if wire.clocked[self.side].is_some() {
+            // backing out before the master's done means the master doesn't get our byte
+            wire.replies[other] = match ready {
+                true => wire.replies[other].or(Some(sb)),
+                false => None,
+            };
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::test_gameboy;
+    use crate::serial::Serial;
+
+    fn linked_serials() -> (Serial, Serial) {
+        let (a, b) = LocalLink::pair();
+        let mut master = Serial::new();
+        let mut slave = Serial::new();
+        master.device = Some(Box::new(a));
+        slave.device = Some(Box::new(b));
+        (master, slave)
+    }
+
+    // ticks both a M-cycle at a time, returns the M-cycle each one's interrupt came on
+    fn run(master: &mut Serial, slave: &mut Serial, cycles: u16) -> (Option<u16>, Option<u16>) {
+        let mut ints = (None, None);
+        master.serial_int = false;
+        slave.serial_int = false;
+        for i in 0..cycles / 4 {
+            let counter = i * 4;
+            master.tick(4, counter);
+            slave.tick(4, counter);
+            if master.serial_int && ints.0.is_none() {
+                ints.0 = Some(i);
+            }
+            if slave.serial_int && ints.1.is_none() {
+                ints.1 = Some(i);
+            }
+        }
+        ints
+    }
+
+    #[test]
+    fn exchange() {
+        let (mut master, mut slave) = linked_serials();
+        slave.write_u8(0xFF01, 0x34);
+        slave.write_u8(0xFF02, 0x80);
+        run(&mut master, &mut slave, 64);
+        master.write_u8(0xFF01, 0x12);
+        master.write_u8(0xFF02, 0x81);
+
+        let (master_int, slave_int) = run(&mut master, &mut slave, 0x2000);
+        assert_eq!(master.read_u8(0xFF01), 0x34);
+        assert_eq!(slave.read_u8(0xFF01), 0x12);
+        // 8 bits at 8192Hz is 1024 M-cycles, and the slave finishes right along with the master
+        assert!(master_int.is_some_and(|i| i > 1000));
+        assert_eq!(master_int, slave_int);
+    }
+
+    #[test]
+    fn slave_not_ready() {
+        let (mut master, mut slave) = linked_serials();
+        master.write_u8(0xFF01, 0x12);
+        master.write_u8(0xFF02, 0x81);
+        let (master_int, slave_int) = run(&mut master, &mut slave, 0x2000);
+        assert!(master_int.is_some());
+        assert_eq!(slave_int, None);
+        assert_eq!(master.read_u8(0xFF01), 0xFF);
+
+        // getting ready afterwards doesn't pick up the byte that already went by
+        slave.write_u8(0xFF01, 0x34);
+        slave.write_u8(0xFF02, 0x80);
+        assert_eq!(run(&mut master, &mut slave, 0x2000), (None, None));
+        assert_eq!(slave.read_u8(0xFF01), 0x34);
+    }
+
+    #[test]
+    fn slave_backs_out() {
+        let (mut master, mut slave) = linked_serials();
+        slave.write_u8(0xFF01, 0x34);
+        slave.write_u8(0xFF02, 0x80);
+        master.write_u8(0xFF01, 0x12);
+        master.write_u8(0xFF02, 0x81);
+        run(&mut master, &mut slave, 0x400);
+        slave.write_u8(0xFF02, 0x00);
+
+        let (master_int, slave_int) = run(&mut master, &mut slave, 0x2000);
+        assert!(master_int.is_some());
+        assert_eq!(slave_int, None);
+        assert_eq!(master.read_u8(0xFF01), 0xFF);
+        assert_eq!(slave.read_u8(0xFF01), 0x34);
+    }
+
+    #[test]
+    fn both_clocking() {
+        let (mut a, mut b) = linked_serials();
+        a.write_u8(0xFF01, 0x12);
+        a.write_u8(0xFF02, 0x81);
+        b.write_u8(0xFF01, 0x34);
+        b.write_u8(0xFF02, 0x81);
+        let (a_int, b_int) = run(&mut a, &mut b, 0x2000);
+        assert!(a_int.is_some() && b_int.is_some());
+        assert_eq!(a.read_u8(0xFF01), 0x34);
+        assert_eq!(b.read_u8(0xFF01), 0x12);
+    }
+
+    // waits for the transfer and stashes what came back in B
+    fn transfer_rom(out: u8, sc: u8) -> [u8; 15] {
+        [
+            0x3E, out, // ld a, out
+            0xE0, 0x01, // ldh (SB), a
+            0x3E, sc, // ld a, sc
+            0xE0, 0x02, // ldh (SC), a
+            0xF0, 0x02, // ldh a, (SC)
+            0x87, // add a (bit 7 into carry)
+            0x38, 0xFB, // jr c, -5
+            0xF0, 0x01, // ldh a, (SB)
+        ]
+    }
+
+    #[test]
+    fn linked_gameboys() {
+        let mut code = transfer_rom(0x12, 0x81).to_vec();
+        code.extend([0x47, 0x18, 0xFE]); // ld b, a; jr -2
+        let mut master = test_gameboy(0x00, &code);
+        let mut code = transfer_rom(0x34, 0x80).to_vec();
+        code.extend([0x47, 0x18, 0xFE]);
+        let mut slave = test_gameboy(0x00, &code);
+
+        let (a, b) = LocalLink::pair();
+        master.cpu.bus.serial.device = Some(Box::new(a));
+        slave.cpu.bus.serial.device = Some(Box::new(b));
+        master.run_frame_linked(&mut slave);
+
+        assert_eq!(master.cpu.registers().b, 0x34);
+        assert_eq!(slave.cpu.registers().b, 0x12);
+    }
+}
@@ -1,37 +1,340 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use eframe::NativeOptions;
 use eframe::egui::ViewportBuilder;
-use std::path::Path;
+use std::{
+    cell::RefCell,
+    fs, io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process,
+};
 
+use crate::apu::Apu;
+use crate::config::Config;
+use crate::cpu::CpuState;
+use crate::disasm::Symbols;
 use crate::gameboy::Gameboy;
 use crate::gui::GUI_SCALE;
 use crate::gui::Gui;
+use crate::input::InputLayer;
+use crate::link::{LinkCable, LocalLink};
+use crate::movie::{Movie, Player, Recorder};
 use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::printer::Printer;
+use crate::serial::Capture;
+use crate::trace::Tracer;
 
 mod apu;
 mod bus;
+mod config;
 mod cpu;
+mod debugger;
+mod disasm;
 mod gameboy;
+mod gdb;
 mod gui;
+mod input;
 mod joypad;
+mod link;
 mod mbc;
+mod movie;
 mod ppu;
+mod printer;
 mod serial;
 mod timer;
+mod trace;
+
+// how long --expect waits when there's no --frames, 10 minutes is plenty for any test rom
+const EXPECT_FRAMES: u64 = 60 * 60 * 10;
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    rom_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    rom_path: Option<String>,
+    /// let the CPU touch VRAM and OAM while the PPU is using them
+    #[arg(long)]
+    no_access_blocking: bool,
+    /// start paused in the command line debugger instead of opening a window
+    #[arg(long)]
+    debug: bool,
+    /// wait for gdb to connect on this port instead of opening a window
+    #[arg(long)]
+    gdb: Option<u16>,
+    /// RGBDS symbol file for debugger labels, defaults to the .sym next to the rom
+    #[arg(long)]
+    sym: Option<String>,
+    /// log every instruction to this file in gameboy doctor's format
+    #[arg(long)]
+    trace: Option<String>,
+    /// only trace instructions with PC in this range, e.g. 0150-3FFF
+    #[arg(long, value_parser = parse_range)]
+    trace_pc: Option<RangeInclusive<u16>>,
+    /// only trace instructions running from this rom bank
+    #[arg(long)]
+    trace_bank: Option<usize>,
+    /// stop tracing after this many lines
+    #[arg(long)]
+    trace_limit: Option<u64>,
+    /// make LY always read 0x90 like gameboy doctor expects
+    #[arg(long)]
+    doctor: bool,
+    /// wait for another emulator to plug into the link port, host:port or unix:path
+    #[arg(long, conflicts_with = "link_connect")]
+    link_listen: Option<String>,
+    /// plug the link cable into an emulator started with --link-listen
+    #[arg(long)]
+    link_connect: Option<String>,
+    /// plug a game boy printer into the link port, prints get saved as pngs in this directory
+    #[arg(long, conflicts_with_all = ["link_listen", "link_connect"])]
+    printer: Option<String>,
+    /// print whatever the game sends over the link port, like test rom results
+    #[arg(long, conflicts_with_all = ["link_listen", "link_connect", "printer"])]
+    serial_out: bool,
+    /// run as fast as possible without opening a window
+    #[arg(long, conflicts_with_all = ["debug", "gdb"])]
+    headless: bool,
+    /// stop after this many frames when running headless
+    #[arg(long, requires = "headless")]
+    frames: Option<u64>,
+    /// exit with an error unless the game prints this over the link port, e.g. "Passed". gives up
+    /// after 10 emulated minutes without --frames
+    #[arg(long, requires = "headless", conflicts_with_all = ["link_listen", "link_connect", "printer"])]
+    expect: Option<String>,
+    /// record the buttons pressed every frame to a movie file
+    #[arg(long, conflicts_with_all = ["play", "link_listen", "link_connect", "player2"])]
+    record: Option<String>,
+    /// play back a movie, when headless it runs until the movie ends and fails if it desyncs
+    #[arg(long, conflicts_with_all = ["link_listen", "link_connect", "player2"])]
+    play: Option<String>,
+    /// settings file to use instead of the one in the user config directory
+    #[arg(long)]
+    config: Option<String>,
+    /// run a second gameboy with this rom next to the first one, linked up in memory
+    #[arg(long, conflicts_with_all = ["link_listen", "link_connect", "printer", "serial_out", "headless", "debug", "gdb"])]
+    player2: Option<String>,
+}
+
+fn parse_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or("range should look like 0150-3FFF")?;
+    let parse = |n: &str| u16::from_str_radix(n.trim_start_matches('$'), 16);
+    match (parse(start), parse(end)) {
+        (Ok(start), Ok(end)) => Ok(start..=end),
+        _ => Err(format!("bad range: {s}")),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// disassemble a rom file without running it
+    Disasm {
+        rom_path: String,
+        /// only disassemble this bank
+        #[arg(long)]
+        bank: Option<usize>,
+        /// RGBDS symbol file for labels, defaults to the .sym next to the rom
+        #[arg(long)]
+        sym: Option<String>,
+    },
+}
+
+// an explicit symbol file has to load, the default one is allowed to not exist
+fn load_symbols(rom_path: &Path, sym: Option<String>) -> Option<Symbols> {
+    match sym {
+        Some(path) => match Symbols::load(Path::new(&path)) {
+            Ok(symbols) => Some(symbols),
+            Err(e) => {
+                println!("couldn't load symbols from {path}: {e}");
+                process::exit(1);
+            }
+        },
+        None => Symbols::load(&rom_path.with_extension("sym")).ok(),
+    }
+}
+
+// returns whether the expected text showed up, or true if there wasn't any
+fn run_headless(
+    gb: &mut Gameboy,
+    mut frames: Option<u64>,
+    expect: Option<&str>,
+    serial_output: &RefCell<String>,
+    mut movie: Option<Movie>,
+) -> bool {
+    if let Some(Movie::Playing(player)) = &movie {
+        frames = frames.or(Some(player.len() as u64));
+    }
+    // waiting for text that never comes shouldn't hang whatever's running us
+    if expect.is_some() {
+        frames = frames.or(Some(EXPECT_FRAMES));
+    }
+    let mut frame = 0;
+    while frames.is_none_or(|frames| frame < frames) {
+        if let Some(movie) = &mut movie {
+            movie.before_frame(gb);
+        }
+        if gb.run_frame()
+            && let Some(movie) = &mut movie
+        {
+            movie.after_frame(gb);
+        }
+        frame += 1;
+        if let Some(expect) = expect
+            && serial_output.borrow().contains(expect)
+        {
+            return true;
+        }
+        if let CpuState::Locked(pc) = gb.cpu_state() {
+            println!("cpu locked at {pc:#06X} after {frame} frames");
+            break;
+        }
+    }
+    if let Some(Movie::Playing(player)) = &movie
+        && player.desynced.is_some()
+    {
+        return false;
+    }
+    if let Some(expect) = expect {
+        println!("didn't see {expect:?} after {frame} frames");
+        return false;
+    }
+    true
 }
 
 fn main() {
     let args = Args::parse();
 
-    let path = Path::new(&args.rom_path);
-    let gb = Gameboy::new(path);
+    if let Some(Command::Disasm {
+        rom_path,
+        bank,
+        sym,
+    }) = args.command
+    {
+        let path = Path::new(&rom_path);
+        let rom = match fs::read(path) {
+            Ok(rom) => rom,
+            Err(e) => {
+                println!("couldn't read {rom_path}: {e}");
+                process::exit(1);
+            }
+        };
+        let symbols = load_symbols(path, sym);
+        // a closed pipe (piping into head and such) is fine, just stop
+        let _ = disasm::disasm_rom(&mut io::stdout().lock(), &rom, bank, symbols.as_ref());
+        return;
+    }
+
+    let config_path = args.config.map(PathBuf::from).or_else(Config::default_path);
+    let config = config_path.as_deref().map(Config::load).unwrap_or_default();
+
+    let path = Path::new(args.rom_path.as_ref().unwrap());
+    let mut gb = Gameboy::new(path);
+    // turbo and macros work the same in every frontend
+    gb.input = InputLayer::new(&config);
+    gb.set_access_blocking(!args.no_access_blocking);
+    gb.debugger.symbols = load_symbols(path, args.sym);
+    gb.cpu.bus.ppu.fake_ly = args.doctor;
+    if let Some(trace_path) = args.trace {
+        let mut tracer = match Tracer::new(Path::new(&trace_path)) {
+            Ok(tracer) => tracer,
+            Err(e) => {
+                println!("couldn't open {trace_path} for the trace: {e}");
+                process::exit(1);
+            }
+        };
+        tracer.pc_range = args.trace_pc;
+        tracer.bank = args.trace_bank;
+        tracer.max_lines = args.trace_limit;
+        gb.cpu.trace = Some(tracer);
+    }
+    let link = match (&args.link_listen, &args.link_connect) {
+        (Some(addr), _) => Some(LinkCable::listen(addr)),
+        (_, Some(addr)) => Some(LinkCable::connect(addr)),
+        _ => None,
+    };
+    match link {
+        Some(Ok(link)) => gb.cpu.bus.serial.device = Some(Box::new(link)),
+        Some(Err(e)) => {
+            println!("couldn't set up the link cable: {e}");
+            process::exit(1);
+        }
+        None => (),
+    }
+    if let Some(dir) = args.printer {
+        gb.cpu.bus.serial.device = Some(Box::new(Printer::new(dir.into())));
+    }
+    let capture = Capture {
+        echo: args.serial_out,
+        ..Default::default()
+    };
+    let serial_output = capture.output.clone();
+    if args.serial_out || args.expect.is_some() {
+        gb.cpu.bus.serial.device = Some(Box::new(capture));
+    }
+    let mut movie = None;
+    if let Some(path) = &args.record {
+        match Recorder::new(Path::new(path), &gb) {
+            Ok(recorder) => movie = Some(Movie::Recording(recorder)),
+            Err(e) => {
+                println!("couldn't record to {path}: {e}");
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = &args.play {
+        match Player::load(Path::new(path), &mut gb) {
+            Ok(player) => movie = Some(Movie::Playing(player)),
+            Err(e) => {
+                println!("couldn't play {path}: {e}");
+                process::exit(1);
+            }
+        }
+    }
+    if args.headless {
+        let passed = run_headless(
+            &mut gb,
+            args.frames,
+            args.expect.as_deref(),
+            &serial_output,
+            movie,
+        );
+        // exit skips drop, and the save should still get written
+        drop(gb);
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if args.debug {
+        debugger::repl(&mut gb);
+        return;
+    }
+    if let Some(port) = args.gdb {
+        if let Err(e) = gdb::serve(&mut gb, port) {
+            println!("gdb server stopped: {e}");
+        }
+        return;
+    }
+    let player2 = args.player2.map(|rom_path| {
+        let path2 = Path::new(&rom_path);
+        // the same rom twice would have both players saving over each other
+        let save_path = match path2 == path {
+            true => path2.with_extension("p2.sav"),
+            false => path2.with_extension("sav"),
+        };
+        // two of the same game playing at once is just noise, only player 1 gets heard
+        let mut gb2 = Gameboy::with_save(path2, save_path, Apu::muted());
+        gb2.input = InputLayer::new(&config);
+        gb2.set_access_blocking(!args.no_access_blocking);
+        let (link1, link2) = LocalLink::pair();
+        gb.cpu.bus.serial.device = Some(Box::new(link1));
+        gb2.cpu.bus.serial.device = Some(Box::new(link2));
+        gb2
+    });
+    let screens = if player2.is_some() { 2 } else { 1 };
     let native_options = NativeOptions {
         viewport: ViewportBuilder::default().with_inner_size([
-            (SCREEN_WIDTH * GUI_SCALE) as f32,
+            (SCREEN_WIDTH * GUI_SCALE * screens) as f32,
             (SCREEN_HEIGHT * GUI_SCALE) as f32,
         ]),
         vsync: true,
@@ -40,6 +343,70 @@ fn main() {
     let _ = eframe::run_native(
         "meow",
         native_options,
-        Box::new(|cc| Ok(Box::new(Gui::new(cc, gb)))),
+        Box::new(|cc| {
+            Ok(Box::new(Gui::new(
+                cc,
+                gb,
+                player2,
+                movie,
+                config,
+                config_path,
+            )))
+        }),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::test_gameboy;
+    use std::rc::Rc;
+
+    // prints "Passed" over the link port like blargg's test roms do
+    fn passing_gameboy() -> (Gameboy, Rc<RefCell<String>>) {
+        let mut code = vec![
+            0x21, 0x50, 0x01, // ld hl, $0150
+            0x2A, // ld a, (hl+)
+            0xB7, // or a
+            0x28, 0x0D, // jr z, done
+            0xE0, 0x01, // ldh (SB), a
+            0x3E, 0x81, // ld a, $81
+            0xE0, 0x02, // ldh (SC), a
+            0xF0, 0x02, // ldh a, (SC)
+            0x87, // add a
+            0x38, 0xFB, // jr c, -5
+            0x18, 0xEF, // jr -17
+            0x18, 0xFE, // done: jr -2
+        ];
+        code.resize(0x50, 0);
+        code.extend(b"Passed\0");
+
+        let mut gb = test_gameboy(0x00, &code);
+        let capture = Capture::default();
+        let output = capture.output.clone();
+        gb.cpu.bus.serial.device = Some(Box::new(capture));
+        (gb, output)
+    }
+
+    #[test]
+    fn headless_expect() {
+        let (mut gb, output) = passing_gameboy();
+        assert!(run_headless(
+            &mut gb,
+            Some(10),
+            Some("Passed"),
+            &output,
+            None
+        ));
+        assert_eq!(*output.borrow(), "Passed");
+
+        let (mut gb, output) = passing_gameboy();
+        assert!(!run_headless(
+            &mut gb,
+            Some(10),
+            Some("Failed"),
+            &output,
+            None
+        ));
+    }
+}
@@ -31,24 +31,7 @@ impl Mbc1 {
     }
 
     pub fn read_rom(&self, addr: u16) -> u8 {
-        let bank = match addr {
-            0x0000..=0x3FFF => match self.mode {
-                false => 0,
-                true => self.ram_bank << 5,
-            },
-            0x4000..=0x7FFF => {
-                let mut bank = match self.rom_bank {
-                    0 => 1,
-                    n => n,
-                };
-                if self.num_rom_banks >= 64 {
-                    bank |= self.ram_bank << 5;
-                }
-                bank
-            }
-            _ => panic!("invalid read from MBC1 rom at address {addr:#06x}"),
-        };
-        self.rom[(bank << 14) | ((addr & 0x3FFF) as usize)]
+        self.rom[(self.rom_bank(addr) << 14) | ((addr & 0x3FFF) as usize)]
     }
 
     pub fn read_ram(&self, addr: u16) -> u8 {
@@ -85,6 +68,26 @@ impl Mbc for Mbc1 {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => match self.mode {
+                false => 0,
+                true => self.ram_bank << 5,
+            },
+            0x4000..=0x7FFF => {
+                let mut bank = match self.rom_bank {
+                    0 => 1,
+                    n => n,
+                };
+                if self.num_rom_banks >= 64 {
+                    bank |= self.ram_bank << 5;
+                }
+                bank
+            }
+            _ => panic!("invalid read from MBC1 rom at address {addr:#06x}"),
+        }
+    }
+
     fn write_u8(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x1FFF => self.ram_enable = val,
@@ -107,4 +110,16 @@ impl Mbc for Mbc1 {
     fn has_battery(&self) -> bool {
         self.has_battery
     }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
@@ -21,15 +21,7 @@ impl Mbc2 {
     }
 
     pub fn read_rom(&self, addr: u16) -> u8 {
-        let bank = match addr {
-            0x0000..=0x3FFF => 0,
-            0x4000..=0x7FFF => match self.rom_bank {
-                0 => 1,
-                n => n,
-            },
-            _ => panic!("invalid read from MBC2 rom at address {addr:#06x}"),
-        };
-        self.rom[((bank as usize) << 14) | ((addr & 0x3FFF) as usize)]
+        self.rom[(self.rom_bank(addr) << 14) | ((addr & 0x3FFF) as usize)]
     }
 
     pub fn read_ram(&self, addr: u16) -> u8 {
@@ -58,6 +50,17 @@ impl Mbc for Mbc2 {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => match self.rom_bank {
+                0 => 1,
+                n => n,
+            },
+            _ => panic!("invalid read from MBC2 rom at address {addr:#06x}"),
+        }
+    }
+
     fn write_u8(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x3FFF => {
@@ -83,4 +86,16 @@ impl Mbc for Mbc2 {
     fn has_battery(&self) -> bool {
         self.has_battery
     }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
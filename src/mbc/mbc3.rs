@@ -28,15 +28,7 @@ impl Mbc3 {
     }
 
     pub fn read_rom(&self, addr: u16) -> u8 {
-        let bank = match addr {
-            0x0000..=0x3FFF => 0,
-            0x4000..=0x7FFF => match self.rom_bank {
-                0 => 1,
-                n => n,
-            },
-            _ => panic!("invalid read from MBC3 rom at address {addr:#06x}"),
-        };
-        self.rom[(bank << 14) | ((addr & 0x3FFF) as usize)]
+        self.rom[(self.rom_bank(addr) << 14) | ((addr & 0x3FFF) as usize)]
     }
 
     pub fn read_ram(&self, addr: u16) -> u8 {
@@ -71,6 +63,17 @@ impl Mbc for Mbc3 {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => match self.rom_bank {
+                0 => 1,
+                n => n,
+            },
+            _ => panic!("invalid read from MBC3 rom at address {addr:#06x}"),
+        }
+    }
+
     fn write_u8(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x1FFF => self.ram_enable = val,
@@ -93,4 +96,16 @@ impl Mbc for Mbc3 {
     fn has_battery(&self) -> bool {
         self.has_battery
     }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
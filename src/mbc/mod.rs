@@ -46,8 +46,13 @@ where
     Self: Debug,
 {
     fn read_u8(&self, addr: u16) -> u8;
+    fn rom_bank(&self, addr: u16) -> usize; // which rom bank is mapped at addr right now
     fn write_u8(&mut self, addr: u16, val: u8);
     fn load_ram(&mut self, ram: Vec<u8>);
     fn dump_ram(&self) -> Vec<u8>;
     fn has_battery(&self) -> bool;
+    // raw access for debug views, ignores banking and ram enable
+    fn rom(&self) -> &[u8];
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
 }
@@ -13,7 +13,12 @@ impl NoMbc {
 
 impl Mbc for NoMbc {
     fn read_u8(&self, addr: u16) -> u8 {
-        self.rom[addr as usize]
+        // there's no ram at 0xA000-0xBFFF, nothing drives the bus so it reads as 0xFF
+        self.rom.get(addr as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        (addr >> 14) as usize
     }
 
     // these don't do anything on an unbanked rom
@@ -28,4 +33,31 @@ impl Mbc for NoMbc {
     fn has_battery(&self) -> bool {
         false
     }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_without_ram() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7FFF] = 0x42;
+        let cart = NoMbc::new(rom);
+        assert_eq!(cart.read_u8(0x7FFF), 0x42);
+        assert_eq!(cart.read_u8(0xA000), 0xFF);
+        assert_eq!(cart.read_u8(0xBFFF), 0xFF);
+    }
 }
@@ -0,0 +1,268 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::gameboy::Gameboy;
+use crate::input::Held;
+
+// movies are plain text so they diff and paste into bug reports nicely:
+//
+//   gameboy movie 1
+//   rom 9f5e2a1c0d3b4e7f
+//   sram 00ff00...          (only for carts with battery backed ram)
+//   ....A...  1b2c3d4e5f607182
+//   ...R....  0a1b2c3d4e5f6071
+//
+// one line per frame with the buttons held during it (UDLRABsS, . for up) and a hash of the
+// screen once it's done. everything starts from power on, with the battery save embedded so the
+// .sav on disk doesn't change how things play out. that's the only state a movie carries, there
+// are no save states to start from
+const HEADER: &str = "gameboy movie 1";
+const BUTTON_CHARS: [char; 8] = ['U', 'D', 'L', 'R', 'A', 'B', 's', 'S'];
+
+// fnv-1a, nothing fancy, it just has to be the same every time
+fn hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn rom_checksum(gb: &Gameboy) -> u64 {
+    hash(gb.cpu.bus.cart.rom().iter().copied())
+}
+
+pub fn frame_hash(gb: &Gameboy) -> u64 {
+    hash(gb.get_frame().iter().flatten().map(|&color| color as u8))
+}
+
+fn format_buttons(mask: u8) -> String {
+    BUTTON_CHARS
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| if mask & (1 << i) != 0 { c } else { '.' })
+        .collect()
+}
+
+fn parse_buttons(s: &str) -> Option<u8> {
+    if s.chars().count() != BUTTON_CHARS.len() {
+        return None;
+    }
+    s.chars()
+        .zip(BUTTON_CHARS)
+        .enumerate()
+        .try_fold(0, |mask, (i, (c, button))| match c {
+            '.' => Some(mask),
+            c if c == button => Some(mask | 1 << i),
+            _ => None,
+        })
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    // has to be called before the first frame runs
+    pub fn new(path: &Path, gb: &Gameboy) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{HEADER}")?;
+        writeln!(out, "rom {:016x}", rom_checksum(gb))?;
+        let sram = gb.cpu.bus.cart.dump_ram();
+        // a battery with no ram to back up has nothing to save
+        if gb.cpu.bus.cart.has_battery() && !sram.is_empty() {
+            let sram: String = sram.iter().map(|b| format!("{b:02x}")).collect();
+            writeln!(out, "sram {sram}")?;
+        }
+        Ok(Self { out })
+    }
+
+    pub fn frame(&mut self, buttons: u8, gb: &Gameboy) {
+        let line = format!("{}  {:016x}", format_buttons(buttons), frame_hash(gb));
+        if let Err(e) = writeln!(self.out, "{line}") {
+            println!("WARNING: couldn't write to the movie: {e}");
+        }
+    }
+}
+
+pub struct Player {
+    frames: Vec<(u8, u64)>,
+    pos: usize,
+    pub desynced: Option<usize>, // first frame where the screen didn't match
+}
+
+impl Player {
+    // checks the movie was made with this rom and sets up the save it started from
+    pub fn load(path: &Path, gb: &mut Gameboy) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not a movie file".to_string());
+        }
+
+        let mut frames = vec![];
+        for (i, line) in lines.enumerate() {
+            let bad_line = || format!("bad movie line {}: {line}", i + 2);
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["rom", checksum] => {
+                    let checksum = u64::from_str_radix(checksum, 16).map_err(|_| bad_line())?;
+                    if checksum != rom_checksum(gb) {
+                        return Err("the movie was recorded with a different rom".to_string());
+                    }
+                }
+                ["sram", sram] => {
+                    let sram = (0..sram.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(sram.get(i..i + 2)?, 16).ok())
+                        .collect::<Option<Vec<u8>>>()
+                        .ok_or_else(bad_line)?;
+                    gb.cpu.bus.cart.load_ram(sram);
+                }
+                [buttons, frame_hash] => {
+                    let buttons = parse_buttons(buttons).ok_or_else(bad_line)?;
+                    let frame_hash = u64::from_str_radix(frame_hash, 16).map_err(|_| bad_line())?;
+                    frames.push((buttons, frame_hash));
+                }
+                [] => (),
+                _ => return Err(bad_line()),
+            }
+        }
+        // playing shouldn't touch the real save
+        gb.save_on_exit = false;
+
+        Ok(Self {
+            frames,
+            pos: 0,
+            desynced: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn done(&self) -> bool {
+        self.pos >= self.frames.len()
+    }
+
+    // buttons for the next frame, None once the movie's over
+    pub fn buttons(&self) -> Option<u8> {
+        self.frames.get(self.pos).map(|&(buttons, _)| buttons)
+    }
+
+    // after a frame ran, compares the screen with what it was when recording
+    pub fn check(&mut self, gb: &Gameboy) {
+        let Some(&(_, expected)) = self.frames.get(self.pos) else {
+            return;
+        };
+        if self.desynced.is_none() && frame_hash(gb) != expected {
+            println!("WARNING: movie desynced at frame {}", self.pos);
+            self.desynced = Some(self.pos);
+        }
+        self.pos += 1;
+        if self.done() {
+            println!("movie finished after {} frames", self.pos);
+        }
+    }
+}
+
+pub enum Movie {
+    Recording(Recorder),
+    Playing(Player),
+}
+
+impl Movie {
+    // the movie's buttons win over whatever's held while it's playing. they already had turbo
+    // and macros applied when they were recorded, so there's nothing else held
+    pub fn before_frame(&mut self, gb: &mut Gameboy) {
+        if let Movie::Playing(player) = self
+            && let Some(buttons) = player.buttons()
+        {
+            gb.held = Held {
+                buttons,
+                ..Default::default()
+            };
+        }
+    }
+
+    // only for frames that actually finished
+    pub fn after_frame(&mut self, gb: &Gameboy) {
+        match self {
+            Movie::Recording(recorder) => recorder.frame(gb.buttons(), gb),
+            Movie::Playing(player) => player.check(gb),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::Apu;
+    use crate::mbc::create_cart;
+    use std::{env, path::PathBuf, process};
+
+    // an MBC1 cart with a battery, `ram_size` is the header's code for it
+    fn battery_gameboy(ram_size: u8, ram: Option<Vec<u8>>) -> Gameboy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]); // jr -2
+        rom[0x0147] = 0x03;
+        rom[0x0149] = ram_size;
+        let mut gb = Gameboy::from_cart(create_cart(rom, ram), PathBuf::new(), Apu::muted());
+        gb.save_on_exit = false;
+        gb
+    }
+
+    fn movie_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("gameboy-{name}-{}.txt", process::id()))
+    }
+
+    fn record(gb: &mut Gameboy, path: &Path, frames: &[u8]) {
+        let mut recorder = Recorder::new(path, gb).unwrap();
+        for &buttons in frames {
+            gb.held.buttons = buttons;
+            gb.run_frame();
+            recorder.frame(gb.buttons(), gb);
+        }
+    }
+
+    fn play(gb: &mut Gameboy, path: &Path) -> Player {
+        let mut player = Player::load(path, gb).unwrap();
+        while let Some(buttons) = player.buttons() {
+            gb.held.buttons = buttons;
+            gb.run_frame();
+            player.check(gb);
+        }
+        player
+    }
+
+    #[test]
+    fn round_trip_battery_without_ram() {
+        let path = movie_path("no-ram");
+        let frames = [0x00, 0x10, 0x30, 0x00, 0x81];
+        record(&mut battery_gameboy(0x00, None), &path, &frames);
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("sram"));
+
+        let player = play(&mut battery_gameboy(0x00, None), &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(player.len(), frames.len());
+        assert!(player.done());
+        assert_eq!(player.desynced, None);
+    }
+
+    #[test]
+    fn round_trip_with_sram() {
+        let path = movie_path("sram");
+        let mut sram = vec![0; 0x2000];
+        sram[..4].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        record(&mut battery_gameboy(0x02, Some(sram)), &path, &[0x00, 0x01]);
+
+        // playback brings its own save, whatever's on disk doesn't matter
+        let mut gb = battery_gameboy(0x02, None);
+        let player = play(&mut gb, &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(player.desynced, None);
+        assert_eq!(gb.cpu.bus.cart.dump_ram()[..4], [0xDE, 0xAD, 0xBE, 0xEF]);
+    }
+}
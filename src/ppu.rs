@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use eframe::egui::Color32;
 use proc_bitfield::bitfield;
 
@@ -16,6 +18,21 @@ pub struct Ppu {
     cycles: u64,
     pub stat_int: bool,
     pub vblank_int: bool,
+    pub access_blocking: bool, // can be turned off for debugging
+    pub fake_ly: bool,         // LY always reads 0x90, gameboy doctor's reference logs expect this
+    stat_line: bool,           // all the STAT sources OR-ed together, interrupts on the rising edge
+
+    // pixel fifo renderer
+    bg_fifo: VecDeque<TilePixel>,
+    obj_fifo: VecDeque<ObjPixel>,
+    fetcher: Fetcher,
+    line_objs: Vec<usize>, // indexes of the objects found by the OAM scan, max 10
+    lx: u8,                // next pixel to be pushed to the screen
+    discard: u8,           // pixels to throw away before pushing, for fine scrolling
+    obj_stall: u8,         // dots left before the pending object is fetched
+    obj_pending: usize,
+    penalty_tile: Option<(bool, u8)>, // last tile an object fetch had to wait on
+    wy_triggered: bool,
 
     lcdc: Lcdc,
     stat: Stat,
@@ -70,11 +87,74 @@ enum TilePixel {
 type Tile = [[TilePixel; 8]; 8];
 
 #[derive(Debug, Clone, Copy)]
-struct Object {
-    y: u8,
-    x: u8,
-    tile: u8,
-    flags: ObjFlags,
+struct ObjPixel {
+    pixel: TilePixel,
+    palette: bool,
+    priority: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+// background/window fetcher, every step but push takes 2 dots
+#[derive(Debug)]
+struct Fetcher {
+    step: FetcherStep,
+    dots: u8,
+    x: u8, // tile column, relative to SCX or to the left edge of the window
+    tile_id: u8,
+    row: [TilePixel; 8],
+    window: bool,
+    startup: bool,
+}
+
+impl Fetcher {
+    fn new() -> Self {
+        Self {
+            step: FetcherStep::Tile,
+            dots: 0,
+            x: 0,
+            tile_id: 0,
+            row: [TilePixel::Zero; 8],
+            window: false,
+            startup: true,
+        }
+    }
+
+    fn start_window(&mut self) {
+        self.step = FetcherStep::Tile;
+        self.dots = 0;
+        self.x = 0;
+        self.window = true;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Object {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: ObjFlags,
+}
+
+fn tileset_index(id: u8, from_lower: bool) -> usize {
+    if from_lower {
+        // 0x8000 method
+        id as usize
+    } else {
+        // 0x8800 method
+        // i could do clever casting stuff here but i don't wanna!
+        if id <= 127 {
+            (id as usize) + 256
+        } else {
+            id as usize
+        }
+    }
 }
 
 fn pixel_to_color(pixel: TilePixel, palette: u8) -> Color {
@@ -143,6 +223,20 @@ impl Ppu {
             cycles: 0,
             stat_int: false,
             vblank_int: false,
+            access_blocking: true,
+            fake_ly: false,
+            stat_line: false,
+
+            bg_fifo: VecDeque::with_capacity(8),
+            obj_fifo: VecDeque::with_capacity(8),
+            fetcher: Fetcher::new(),
+            line_objs: Vec::with_capacity(10),
+            lx: 0,
+            discard: 0,
+            obj_stall: 0,
+            obj_pending: 0,
+            penalty_tile: None,
+            wy_triggered: false,
 
             lcdc: 0b10100011.into(),
             stat: 0.into(),
@@ -161,12 +255,15 @@ impl Ppu {
 
     pub fn read_u8(&self, addr: u16) -> u8 {
         match addr {
+            0x8000..=0x9FFF if self.vram_blocked() => 0xFF,
             0x8000..=0x9FFF => self.read_vram(addr),
+            0xFE00..=0xFE9F if self.oam_blocked() => 0xFF,
             0xFE00..=0xFE9F => self.read_oam(addr),
             0xFF40 => self.lcdc.into(),
-            0xFF41 => self.stat.into(),
+            0xFF41 => u8::from(self.stat) | 0x80, // bit 7 is unused and always set
             0xFF42 => self.scy,
             0xFF43 => self.scx,
+            0xFF44 if self.fake_ly => 0x90,
             0xFF44 => self.ly,
             0xFF45 => self.lyc,
             0xFF47 => self.bgp,
@@ -180,10 +277,12 @@ impl Ppu {
 
     pub fn write_u8(&mut self, addr: u16, val: u8) {
         match addr {
+            0x8000..=0x9FFF if self.vram_blocked() => (),
             0x8000..=0x9FFF => self.write_vram(addr, val),
+            0xFE00..=0xFE9F if self.oam_blocked() => (),
             0xFE00..=0xFE9F => self.write_oam(addr, val),
-            0xFF40 => self.lcdc = val.into(),
-            0xFF41 => self.stat = val.into(),
+            0xFF40 => self.set_lcdc(val),
+            0xFF41 => self.write_stat(val),
             0xFF42 => self.scy = val,
             0xFF43 => self.scx = val,
             0xFF44 => (), // can't write to ly, do nothing
@@ -197,20 +296,68 @@ impl Ppu {
         }
     }
 
+    // for debug tools, vram and oam are always reachable this way
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF => self.read_vram(addr),
+            0xFE00..=0xFE9F => self.read_oam(addr),
+            _ => self.read_u8(addr),
+        }
+    }
+
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.write_vram(addr, val),
+            0xFE00..=0xFE9F => self.write_oam(addr, val),
+            _ => self.write_u8(addr, val),
+        }
+    }
+
+    // the ppu owns vram while drawing and oam while scanning or drawing
+    fn vram_blocked(&self) -> bool {
+        self.access_blocking && self.state == PpuState::Draw
+    }
+
+    fn oam_blocked(&self) -> bool {
+        self.access_blocking && matches!(self.state, PpuState::OAMScan | PpuState::Draw)
+    }
+
     fn set_ly(&mut self, val: u8) {
         self.ly = val;
         self.stat.set_lyc_eq_ly(self.ly == self.lyc);
-        if self.ly == self.lyc && self.stat.lyc_int_select() {
-            self.stat_int = true;
-        }
+        self.update_stat_line();
     }
 
     fn set_lyc(&mut self, val: u8) {
         self.lyc = val;
         self.stat.set_lyc_eq_ly(self.ly == self.lyc);
-        if self.ly == self.lyc && self.stat.lyc_int_select() {
+        self.update_stat_line();
+    }
+
+    fn write_stat(&mut self, val: u8) {
+        // mode and LY=LYC bits are read only
+        self.stat = ((val & 0x78) | (u8::from(self.stat) & 0x07)).into();
+        self.update_stat_line();
+    }
+
+    // only a low to high transition of the line requests an interrupt, so one source
+    // being active "blocks" the others from firing
+    fn update_stat_line(&mut self) {
+        if !self.lcdc.lcd_ppu_enable() {
+            self.stat_line = false;
+            return;
+        }
+        let mode = self.stat.ppu_mode();
+        let line = (self.stat.lyc_int_select() && self.stat.lyc_eq_ly())
+            || (self.stat.mode_0_int_select() && mode == PpuState::HBlank as u8)
+            || (self.stat.mode_1_int_select() && mode == PpuState::VBlank as u8)
+            || (self.stat.mode_2_int_select() && mode == PpuState::OAMScan as u8)
+            // the mode 2 source also fires when line 144 starts
+            || (self.stat.mode_2_int_select() && self.ly == 144 && self.cycles == 0);
+        if line && !self.stat_line {
             self.stat_int = true;
         }
+        self.stat_line = line;
     }
 
     fn read_vram(&self, addr: u16) -> u8 {
@@ -253,6 +400,11 @@ impl Ppu {
         self.oam[(addr - 0xFE00) as usize]
     }
 
+    // OAM DMA doesn't care what the ppu is doing
+    pub fn dma_write(&mut self, index: usize, val: u8) {
+        self.write_oam(0xFE00 + index as u16, val);
+    }
+
     fn write_oam(&mut self, addr: u16, val: u8) {
         let index = (addr - 0xFE00) as usize;
         self.oam[index] = val;
@@ -268,18 +420,7 @@ impl Ppu {
     }
 
     fn index_to_tile(&self, id: u8, from_lower: bool) -> &Tile {
-        if from_lower {
-            // 0x8000 method
-            &self.tileset[id as usize]
-        } else {
-            // 0x8800 method
-            // i could do clever casting stuff here but i don't wanna!
-            if id <= 127 {
-                &self.tileset[(id as usize) + 256]
-            } else {
-                &self.tileset[id as usize]
-            }
-        }
+        &self.tileset[tileset_index(id, from_lower)]
     }
 
     fn get_tileid(&self, x: u8, y: u8, from_upper: bool) -> u8 {
@@ -290,29 +431,63 @@ impl Ppu {
         }
     }
 
+    // for the debug views
+    pub fn tile_colors(&self, index: usize, palette: u8) -> [[Color; 8]; 8] {
+        self.tileset[index].map(|row| row.map(|pixel| pixel_to_color(pixel, palette)))
+    }
+
+    // which of the 384 tiles sits at x, y in a tilemap, using the addressing LCDC picks for
+    // the background and window
+    pub fn map_tile(&self, upper_map: bool, x: u8, y: u8) -> usize {
+        let id = self.get_tileid(x, y, upper_map);
+        tileset_index(id, self.lcdc.bg_window_tiles())
+    }
+
+    pub fn objects(&self) -> &[Object; 40] {
+        &self.objects
+    }
+
     pub fn tick(&mut self, cycles: u64) {
         if !self.lcdc.lcd_ppu_enable() {
-            self.stat.set_ppu_mode(0);
             return;
         }
-        self.cycles += cycles;
+        // everything happens one dot at a time now so mid-scanline writes show up
+        for _ in 0..cycles {
+            self.dot();
+        }
+    }
+
+    fn dot(&mut self) {
+        match self.state {
+            PpuState::OAMScan => {
+                // one object is checked every 2 dots
+                if self.cycles.is_multiple_of(2) {
+                    self.scan_obj((self.cycles / 2) as usize);
+                }
+            }
+            PpuState::Draw => self.draw_dot(),
+            PpuState::HBlank | PpuState::VBlank => (),
+        }
+
+        self.cycles += 1;
         match self.state {
             PpuState::OAMScan => {
                 if self.cycles >= 80 {
-                    self.cycles -= 80;
                     self.change_state(PpuState::Draw);
                 }
             }
             PpuState::Draw => {
-                // technically variable but i don't care for now lol
-                if self.cycles >= 172 {
-                    self.cycles -= 172;
+                // mode 3 ends by itself once the whole line has been pushed out
+                if self.lx as usize >= SCREEN_WIDTH {
                     self.change_state(PpuState::HBlank);
                 }
             }
             PpuState::HBlank => {
-                if self.cycles >= 204 {
-                    self.cycles -= 204;
+                if self.cycles >= 456 {
+                    self.cycles -= 456;
+                    if self.fetcher.window {
+                        self.wly += 1;
+                    }
                     self.set_ly(self.ly + 1);
                     if self.ly >= 144 {
                         self.change_state(PpuState::VBlank);
@@ -322,13 +497,18 @@ impl Ppu {
                 }
             }
             PpuState::VBlank => {
+                // LY only reads 153 for a few dots, the rest of that line is already LY=0
+                if self.ly == 153 && self.cycles == 4 {
+                    self.set_ly(0);
+                }
                 if self.cycles >= 456 {
                     self.cycles -= 456;
-                    self.set_ly(self.ly + 1);
-                    if self.ly > 153 {
-                        self.set_ly(0);
+                    if self.ly == 0 {
                         self.wly = 0; //reset window line counter
+                        self.wy_triggered = false;
                         self.change_state(PpuState::OAMScan);
+                    } else {
+                        self.set_ly(self.ly + 1);
                     }
                 }
             }
@@ -338,14 +518,9 @@ impl Ppu {
     fn change_state(&mut self, state: PpuState) {
         match state {
             PpuState::HBlank => {
-                self.draw_line();
-
                 self.state = PpuState::HBlank;
                 self.stat.set_ppu_mode(PpuState::HBlank as u8);
-
-                if self.stat.mode_0_int_select() {
-                    self.stat_int = true;
-                }
+                self.update_stat_line();
             }
             PpuState::VBlank => {
                 // move frame buffer onto application window and clear frame buffer
@@ -356,159 +531,395 @@ impl Ppu {
                 self.stat.set_ppu_mode(PpuState::VBlank as u8);
 
                 self.vblank_int = true;
-                if self.stat.mode_1_int_select() {
-                    self.stat_int = true;
-                }
+                self.update_stat_line();
             }
             PpuState::OAMScan => {
+                self.line_objs.clear();
+                if self.ly == self.wy {
+                    self.wy_triggered = true;
+                }
+
                 self.state = PpuState::OAMScan;
                 self.stat.set_ppu_mode(PpuState::OAMScan as u8);
-
-                if self.stat.mode_2_int_select() {
-                    self.stat_int = true;
-                }
+                self.update_stat_line();
             }
             PpuState::Draw => {
+                self.bg_fifo.clear();
+                self.obj_fifo.clear();
+                self.fetcher = Fetcher::new();
+                self.lx = 0;
+                self.discard = self.scx % 8;
+                self.obj_stall = 0;
+                self.penalty_tile = None;
+
                 self.state = PpuState::Draw;
                 self.stat.set_ppu_mode(PpuState::Draw as u8);
+                self.update_stat_line();
             }
         }
     }
 
-    fn draw_line(&mut self) {
-        if self.lcdc.bg_window_enable() {
-            self.draw_bg();
-        }
-        if self.lcdc.obj_enable() {
-            self.draw_obj();
+    fn set_lcdc(&mut self, val: u8) {
+        let was_enabled = self.lcdc.lcd_ppu_enable();
+        self.lcdc = val.into();
+        match (was_enabled, self.lcdc.lcd_ppu_enable()) {
+            (true, false) => self.turn_off(),
+            (false, true) => self.turn_on(),
+            _ => (),
         }
     }
 
-    fn draw_bg(&mut self) {
-        let scroll_x = self.scx;
-        let scroll_y = self.scy;
-        let win_x = (self.wx as i16) - 7;
-        let win_y = self.wy;
+    // screen goes blank and the ppu sits at the start of line 0
+    fn turn_off(&mut self) {
+        self.cycles = 0;
+        self.ly = 0;
+        self.stat.set_lyc_eq_ly(self.lyc == 0);
+        self.stat_line = false;
+        self.wly = 0;
+        self.wy_triggered = false;
+        self.state = PpuState::HBlank;
+        self.stat.set_ppu_mode(PpuState::HBlank as u8);
+        self.frame.fill([Color::White; SCREEN_WIDTH]);
+        self.frame_buffer.fill([Color::White; SCREEN_WIDTH]);
+    }
 
-        let tileset = self.lcdc.bg_window_tiles();
-        let bg_tilemap = self.lcdc.bg_tilemap();
-        let win_tilemap = self.lcdc.window_tilemap();
-        let use_window = self.lcdc.window_enable() && self.ly >= win_y;
+    fn turn_on(&mut self) {
+        self.cycles = 0;
+        self.change_state(PpuState::OAMScan);
+    }
 
-        let mut pixel;
-        let mut window_rendered = false;
+    pub fn in_vblank(&self) -> bool {
+        self.state == PpuState::VBlank
+    }
 
-        for line_x in 0u8..160u8 {
-            let x;
-            let y;
-            let tilemap;
+    // STOP turns the LCD off without touching LCDC, so it comes back by itself afterwards
+    pub fn stop(&mut self) {
+        self.turn_off();
+    }
 
-            if use_window && ((line_x as i16) >= win_x) {
-                // render window
-                window_rendered = true;
-                x = ((line_x as i16) - win_x) as u8;
-                y = self.wly;
-                tilemap = win_tilemap;
-            } else {
-                // render background
-                x = line_x.wrapping_add(scroll_x);
-                y = self.ly.wrapping_add(scroll_y);
-                tilemap = bg_tilemap;
-            }
+    pub fn resume(&mut self) {
+        if self.lcdc.lcd_ppu_enable() {
+            self.turn_on();
+        }
+    }
 
-            let map_x = x / 8;
-            let tile_x = x % 8;
-            let map_y = y / 8;
-            let tile_y = y % 8;
+    fn obj_height(&self) -> i16 {
+        if self.lcdc.obj_size() { 16 } else { 8 }
+    }
 
-            let tile_id = self.get_tileid(map_x, map_y, tilemap);
-            let tile = self.index_to_tile(tile_id, tileset);
-            pixel = tile[tile_y as usize][tile_x as usize];
-            self.frame_buffer[self.ly as usize][line_x as usize] = pixel_to_color(pixel, self.bgp);
+    // X isn't looked at here, so objects that are off screen still use up one of the 10 slots
+    fn scan_obj(&mut self, index: usize) {
+        if self.line_objs.len() >= 10 {
+            return;
         }
-        if window_rendered {
-            self.wly += 1;
+        let obj_y = (self.objects[index].y as i16) - 16;
+        let ly = self.ly as i16;
+        if ly >= obj_y && ly < obj_y + self.obj_height() {
+            self.line_objs.push(index);
         }
     }
 
-    fn draw_obj(&mut self) {
-        let ly = self.ly as i16; // makes the math easier
-        let obj_height = if self.lcdc.obj_size() { 16 } else { 8 };
-
-        // objects to be rendered on this line, max 10
-        let mut line_objs: Vec<&Object> = vec![];
-        for obj in self.objects.iter() {
-            let obj_y = (obj.y as i16) - 16;
-            if ly >= obj_y && ly < obj_y + obj_height {
-                line_objs.push(obj);
-                if line_objs.len() >= 10 {
-                    break;
-                }
+    // one dot of mode 3
+    fn draw_dot(&mut self) {
+        // still busy fetching an object, everything else waits
+        if self.obj_stall == 0
+            && self.lcdc.obj_enable()
+            && self.discard == 0
+            && let Some(pos) = self.next_line_obj()
+        {
+            self.obj_stall = self.obj_penalty(self.line_objs[pos]);
+            self.obj_pending = self.line_objs.remove(pos);
+        }
+        if self.obj_stall > 0 {
+            self.obj_stall -= 1;
+            if self.obj_stall == 0 {
+                self.fetch_obj(self.obj_pending);
             }
+            return;
         }
 
-        for line_x in 0..160 {
-            // find list of objects to try drawing
-            let mut obj_list = vec![];
-            for obj in line_objs.iter() {
-                let obj_x = (obj.x as i16) - 8;
-                if line_x >= obj_x && line_x < obj_x + 8 {
-                    obj_list.push(*obj);
-                }
-            }
+        if !self.fetcher.window
+            && self.lcdc.window_enable()
+            && self.wy_triggered
+            && (self.lx as u16) + 7 >= self.wx as u16
+        {
+            // throw out whatever background was queued and start fetching the window
+            self.bg_fifo.clear();
+            self.fetcher.start_window();
+            // the window isn't scrolled, so SCX's fine scroll doesn't apply to it. only a
+            // window hanging off the left edge has pixels to throw away
+            self.discard = 7u8.saturating_sub(self.wx);
+        }
 
-            obj_list.sort_by_key(|o| o.x);
-            let mut pixel = TilePixel::Zero;
-            let mut palette = 0;
-
-            // draw object if there is one
-            for obj in obj_list {
-                // only draw if it's not set to priority or if it can draw when set to priority
-                if !obj.flags.priority()
-                    || self.frame_buffer[self.ly as usize][line_x as usize] == Color::White
-                {
-                    let obj_x = (obj.x as i16) - 8;
-                    let obj_y = (obj.y as i16) - 16;
-                    let mut tile_x = line_x - obj_x;
-                    let mut tile_y = ly - obj_y;
-
-                    // flip tile if necessary
-                    if obj.flags.x_flip() {
-                        tile_x = 7 - tile_x;
-                    }
-                    if obj.flags.y_flip() {
-                        tile_y = (obj_height - 1) - tile_y;
-                    }
+        self.tick_fetcher();
+        self.push_pixel();
+    }
 
-                    let tile = if self.lcdc.obj_size() {
-                        let tile_ind = obj.tile & !1;
-                        if tile_y > 7 {
-                            tile_y -= 8;
-                            self.index_to_tile(tile_ind + 1, true)
-                        } else {
-                            self.index_to_tile(tile_ind, true)
-                        }
-                    } else {
-                        self.index_to_tile(obj.tile, true)
-                    };
+    // position in line_objs of the next object that starts at the current pixel. everything
+    // hanging off the left edge starts at pixel 0, so go by lowest X and then lowest OAM index
+    // to get the DMG priority order since the first object fetched keeps overlapping pixels
+    fn next_line_obj(&self) -> Option<usize> {
+        self.line_objs
+            .iter()
+            .enumerate()
+            .filter(|&(_, &i)| self.objects[i].x.max(8) - 8 == self.lx)
+            .min_by_key(|&(_, &i)| (self.objects[i].x, i))
+            .map(|(pos, _)| pos)
+    }
+
+    // how many dots fetching an object holds up mode 3, see pandocs "mode 3 length"
+    fn obj_penalty(&mut self, index: usize) -> u8 {
+        let obj_x = self.objects[index].x;
+        if obj_x == 0 {
+            return 11;
+        }
+        // over the window 255 - WX stands in for SCX
+        let x = if self.fetcher.window {
+            obj_x.wrapping_add(255 - self.wx)
+        } else {
+            obj_x.wrapping_add(self.scx)
+        };
+        // only the first object on a given tile has to wait for the background fetch
+        let tile = (self.fetcher.window, x / 8);
+        if self.penalty_tile == Some(tile) {
+            return 6;
+        }
+        self.penalty_tile = Some(tile);
+        6 + 5u8.saturating_sub(x % 8)
+    }
 
-                    palette = if obj.flags.dmg_palette() {
-                        self.obp1
+    fn tick_fetcher(&mut self) {
+        match self.fetcher.step {
+            FetcherStep::Tile => {
+                self.fetcher.dots += 1;
+                if self.fetcher.dots == 2 {
+                    self.fetcher.tile_id = self.fetch_tile_id();
+                    self.fetcher.dots = 0;
+                    self.fetcher.step = FetcherStep::DataLow;
+                }
+            }
+            FetcherStep::DataLow => {
+                self.fetcher.dots += 1;
+                if self.fetcher.dots == 2 {
+                    self.fetcher.dots = 0;
+                    self.fetcher.step = FetcherStep::DataHigh;
+                }
+            }
+            FetcherStep::DataHigh => {
+                self.fetcher.dots += 1;
+                if self.fetcher.dots == 2 {
+                    let row = if self.fetcher.window {
+                        self.wly % 8
                     } else {
-                        self.obp0
+                        self.ly.wrapping_add(self.scy) % 8
                     };
-
-                    pixel = tile[tile_y as usize][tile_x as usize];
-                    if pixel != TilePixel::Zero {
-                        break;
+                    let tileset = self.lcdc.bg_window_tiles();
+                    self.fetcher.row =
+                        self.index_to_tile(self.fetcher.tile_id, tileset)[row as usize];
+                    self.fetcher.dots = 0;
+                    self.fetcher.step = FetcherStep::Push;
+                    // the very first fetch of a line gets thrown away, that one waits a dot
+                    if !self.fetcher.startup {
+                        self.fetcher_push();
                     }
                 }
             }
+            FetcherStep::Push => self.fetcher_push(),
+        }
+    }
+
+    fn fetch_tile_id(&self) -> u8 {
+        if self.fetcher.window {
+            self.get_tileid(
+                self.fetcher.x & 31,
+                self.wly / 8,
+                self.lcdc.window_tilemap(),
+            )
+        } else {
+            let map_x = ((self.scx / 8) + self.fetcher.x) & 31;
+            let map_y = self.ly.wrapping_add(self.scy) / 8;
+            self.get_tileid(map_x, map_y, self.lcdc.bg_tilemap())
+        }
+    }
+
+    fn fetcher_push(&mut self) {
+        if self.fetcher.startup {
+            self.fetcher.startup = false;
+            self.fetcher.step = FetcherStep::Tile;
+            return;
+        }
+        if self.bg_fifo.is_empty() {
+            self.bg_fifo.extend(self.fetcher.row);
+            self.fetcher.x = self.fetcher.x.wrapping_add(1);
+            self.fetcher.step = FetcherStep::Tile;
+        }
+    }
+
+    fn fetch_obj(&mut self, index: usize) {
+        let obj = self.objects[index];
+        let obj_height = self.obj_height();
+
+        let mut tile_y = (self.ly as i16) - ((obj.y as i16) - 16);
+        if obj.flags.y_flip() {
+            tile_y = (obj_height - 1) - tile_y;
+        }
+        let tile = if self.lcdc.obj_size() {
+            let tile_ind = obj.tile & !1;
+            if tile_y > 7 {
+                tile_y -= 8;
+                self.index_to_tile(tile_ind + 1, true)
+            } else {
+                self.index_to_tile(tile_ind, true)
+            }
+        } else {
+            self.index_to_tile(obj.tile, true)
+        };
+        let mut row = tile[tile_y as usize];
+        if obj.flags.x_flip() {
+            row.reverse();
+        }
 
-            if pixel != TilePixel::Zero {
-                self.frame_buffer[self.ly as usize][line_x as usize] =
-                    pixel_to_color(pixel, palette);
+        // objects hanging off the left edge only show their right side
+        let skip = 8 - (obj.x.min(8) as usize);
+        while self.obj_fifo.len() < 8 {
+            self.obj_fifo.push_back(ObjPixel {
+                pixel: TilePixel::Zero,
+                palette: false,
+                priority: false,
+            });
+        }
+        for (slot, &pixel) in row.iter().skip(skip).enumerate() {
+            // whoever got here first keeps the pixel unless they're transparent
+            if self.obj_fifo[slot].pixel == TilePixel::Zero {
+                self.obj_fifo[slot] = ObjPixel {
+                    pixel,
+                    palette: obj.flags.dmg_palette(),
+                    priority: obj.flags.priority(),
+                };
             }
         }
     }
+
+    fn push_pixel(&mut self) {
+        let Some(bg) = self.bg_fifo.pop_front() else {
+            return;
+        };
+        let obj = self.obj_fifo.pop_front();
+        if self.discard > 0 {
+            self.discard -= 1;
+            return;
+        }
+
+        // on DMG this bit blanks both background and window
+        let (bg, mut color) = if self.lcdc.bg_window_enable() {
+            (bg, pixel_to_color(bg, self.bgp))
+        } else {
+            (TilePixel::Zero, Color::White)
+        };
+
+        // priority objects only show over background color index 0, whatever the palette
+        if let Some(obj) = obj
+            && obj.pixel != TilePixel::Zero
+            && self.lcdc.obj_enable()
+            && (!obj.priority || bg == TilePixel::Zero)
+        {
+            let palette = if obj.palette { self.obp1 } else { self.obp0 };
+            color = pixel_to_color(obj.pixel, palette);
+        }
+
+        self.frame_buffer[self.ly as usize][self.lx as usize] = color;
+        self.lx += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tile 0 is blank, tile 1 has only its leftmost column set so x positions are easy to see
+    fn test_ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.access_blocking = false;
+        for row in 0..8 {
+            ppu.write_u8(0x8010 + row * 2, 0x80);
+            ppu.write_u8(0x8011 + row * 2, 0x80);
+        }
+        ppu.write_u8(0xFF47, 0xE4);
+        ppu
+    }
+
+    fn run_frames(ppu: &mut Ppu, frames: u64) {
+        ppu.tick(70224 * frames);
+    }
+
+    fn black_columns(ppu: &Ppu, y: usize) -> Vec<usize> {
+        (0..SCREEN_WIDTH)
+            .filter(|&x| ppu.frame[y][x] == Color::Black)
+            .collect()
+    }
+
+    #[test]
+    fn fine_scroll() {
+        let mut ppu = test_ppu();
+        for addr in 0x9800..0x9C00 {
+            ppu.write_u8(addr, 1);
+        }
+        ppu.write_u8(0xFF43, 3);
+        ppu.write_u8(0xFF40, 0x91);
+        run_frames(&mut ppu, 2);
+        assert_eq!(black_columns(&ppu, 0)[..3], [5, 13, 21]);
+    }
+
+    #[test]
+    fn window_ignores_fine_scroll() {
+        let mut ppu = test_ppu();
+        // background blank, window map at 0x9C00 full of tile 1
+        for addr in 0x9C00..0xA000 {
+            ppu.write_u8(addr, 1);
+        }
+        ppu.write_u8(0xFF43, 3);
+        ppu.write_u8(0xFF4A, 0);
+        ppu.write_u8(0xFF4B, 7);
+        ppu.write_u8(0xFF40, 0xF1);
+        run_frames(&mut ppu, 2);
+        assert_eq!(black_columns(&ppu, 0)[..3], [0, 8, 16]);
+
+        // WX below 7 pushes the window off the left edge
+        ppu.write_u8(0xFF4B, 5);
+        run_frames(&mut ppu, 1);
+        assert_eq!(black_columns(&ppu, 0)[..3], [6, 14, 22]);
+
+        // and further right it starts at WX - 7
+        ppu.write_u8(0xFF4B, 27);
+        run_frames(&mut ppu, 1);
+        assert_eq!(black_columns(&ppu, 0)[..3], [20, 28, 36]);
+    }
+
+    #[test]
+    fn line_153_reads_as_0() {
+        let mut ppu = test_ppu();
+        ppu.write_u8(0xFF45, 0);
+        ppu.write_u8(0xFF41, 0x40); // LYC=LY source only
+        ppu.write_u8(0xFF40, 0x91);
+        while ppu.read_u8(0xFF44) != 153 {
+            ppu.tick(1);
+        }
+        ppu.stat_int = false;
+
+        let mut dots = 0;
+        while ppu.read_u8(0xFF44) == 153 {
+            ppu.tick(1);
+            dots += 1;
+        }
+        assert_eq!(dots, 4);
+        assert_eq!(ppu.read_u8(0xFF44), 0);
+        assert!(ppu.stat_int);
+        ppu.stat_int = false;
+
+        // the line stays high through the rest of 153 and all of line 0, even as
+        // the mode changes under it
+        ppu.tick(456 - 4 + 456);
+        assert_eq!(ppu.read_u8(0xFF44), 1);
+        assert!(!ppu.stat_int);
+    }
 }
@@ -0,0 +1,400 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use crate::ppu::SCREEN_WIDTH;
+use crate::serial::SerialDevice;
+
+const TILES_PER_ROW: usize = SCREEN_WIDTH / 8;
+const BUFFER_SIZE: usize = 0x2000; // 8KB, enough for 160x200 pixels
+
+// how many STATUS packets report the printer as busy after a PRINT. games wait to see the busy
+// bit go up and then come back down, how long it takes doesn't matter
+const PRINT_TIME: u8 = 4;
+
+// paper fed per unit of margin in the PRINT command
+const MARGIN_LINES: usize = 8;
+
+// status bits
+const CHECKSUM_ERROR: u8 = 1 << 0;
+const PRINTING: u8 = 1 << 1;
+const IMAGE_FULL: u8 = 1 << 2;
+const UNPROCESSED: u8 = 1 << 3;
+
+// where in a packet the next byte goes. packets look like
+// 88 33 | command | compression | length (le) | data | checksum (le) | 00 00
+// and the printer answers the last two bytes with 0x81 and its status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLo,
+    LengthHi,
+    Data,
+    ChecksumLo,
+    ChecksumHi,
+    KeepAlive,
+    Status,
+}
+
+// game boy printer, prints end up as png files in `out_dir`
+#[derive(Debug)]
+pub struct Printer {
+    out_dir: PathBuf,
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    reply: u8,
+
+    buffer: Vec<u8>, // tile data waiting to be printed, 20 tiles per row
+    status: u8,
+    printing: u8,  // STATUS packets left until the current print is done
+    page: Vec<u8>, // printed rows of shades, strips get added until one has a bottom margin
+}
+
+impl Printer {
+    pub fn new(out_dir: PathBuf) -> Self {
+        Self {
+            out_dir,
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: vec![],
+            checksum: 0,
+            reply: 0,
+            buffer: vec![],
+            status: 0,
+            printing: 0,
+            page: vec![],
+        }
+    }
+
+    // returns what the printer shifts back for this byte
+    fn receive(&mut self, val: u8) -> u8 {
+        use State::*;
+        match self.state {
+            Magic1 => {
+                if val == 0x88 {
+                    self.state = Magic2;
+                }
+            }
+            Magic2 => self.state = if val == 0x33 { Command } else { Magic1 },
+            Command => {
+                self.command = val;
+                self.checksum = val as u16;
+                self.state = Compression;
+            }
+            Compression => {
+                self.compressed = val & 1 != 0;
+                self.checksum = self.checksum.wrapping_add(val as u16);
+                self.state = LengthLo;
+            }
+            LengthLo => {
+                self.length = val as u16;
+                self.checksum = self.checksum.wrapping_add(val as u16);
+                self.state = LengthHi;
+            }
+            LengthHi => {
+                self.length |= (val as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(val as u16);
+                self.data.clear();
+                self.state = if self.length == 0 { ChecksumLo } else { Data };
+            }
+            Data => {
+                self.data.push(val);
+                self.checksum = self.checksum.wrapping_add(val as u16);
+                if self.data.len() >= self.length as usize {
+                    self.state = ChecksumLo;
+                }
+            }
+            ChecksumLo => {
+                self.checksum ^= val as u16;
+                self.state = ChecksumHi;
+            }
+            ChecksumHi => {
+                self.checksum ^= (val as u16) << 8;
+                self.state = KeepAlive;
+            }
+            KeepAlive => {
+                // the whole packet's here, so the status byte that comes next can reflect it
+                self.run_command();
+                self.state = Status;
+                return 0x81;
+            }
+            Status => {
+                self.state = Magic1;
+                return self.status;
+            }
+        }
+        0x00
+    }
+
+    fn run_command(&mut self) {
+        // checksum ended up as computed ^ received, so it's 0 if they matched
+        if self.checksum != 0 {
+            self.status |= CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !CHECKSUM_ERROR;
+
+        match self.command {
+            // INIT
+            0x01 => {
+                self.buffer.clear();
+                self.status = 0;
+                self.printing = 0;
+            }
+            // PRINT: sheets, margins, palette, exposure. exposure only changes how dark the ink
+            // is on real paper, so it's ignored
+            0x02 => {
+                if let [sheets, margins, palette, _] = self.data[..] {
+                    self.print(sheets, margins, palette);
+                }
+                self.buffer.clear();
+                self.printing = PRINT_TIME;
+            }
+            // DATA, an empty one just marks the end of the image
+            0x04 => {
+                let data = match self.compressed {
+                    true => decompress(&self.data),
+                    false => self.data.clone(),
+                };
+                let space = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(space));
+            }
+            // STATUS
+            0x0F => self.printing = self.printing.saturating_sub(1),
+            cmd => println!("WARNING: unknown printer command {cmd:#04X}"),
+        }
+
+        self.status &= !(PRINTING | IMAGE_FULL | UNPROCESSED);
+        if self.printing > 0 {
+            self.status |= PRINTING;
+        }
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.status |= IMAGE_FULL;
+        }
+        if !self.buffer.is_empty() {
+            self.status |= UNPROCESSED;
+        }
+    }
+
+    // renders the buffer onto the page. margins are in the upper (before) and lower (after)
+    // nibble, the palette maps color indexes to shades like BGP does. 0 sheets only feeds the
+    // paper, any other number prints the image once since more copies would be the same png
+    fn print(&mut self, sheets: u8, margins: u8, palette: u8) {
+        self.feed((margins >> 4) as usize * MARGIN_LINES);
+
+        // some games leave the palette at 0, the printer takes that as the usual one
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let rows = match sheets {
+            0 => 0,
+            _ => self.buffer.len() / (TILES_PER_ROW * 16),
+        };
+        for tile_row in 0..rows {
+            for y in 0..8 {
+                for x in 0..SCREEN_WIDTH {
+                    let tile = tile_row * TILES_PER_ROW + x / 8;
+                    let lo = self.buffer[tile * 16 + y * 2];
+                    let hi = self.buffer[tile * 16 + y * 2 + 1];
+                    let bit = 7 - (x % 8);
+                    let index = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
+                    let shade = (palette >> (index * 2)) & 0b11;
+                    self.page.push(255 - shade * 85);
+                }
+            }
+        }
+
+        let after = (margins & 0xF) as usize;
+        self.feed(after * MARGIN_LINES);
+        // no margin after means the game's going to keep printing on the same piece of paper
+        if after > 0 {
+            self.save_page();
+        }
+    }
+
+    fn feed(&mut self, lines: usize) {
+        self.page
+            .extend(std::iter::repeat_n(0xFF, lines * SCREEN_WIDTH));
+    }
+
+    fn save_page(&mut self) {
+        let page = std::mem::take(&mut self.page);
+        if let Err(e) = self.write_png(&page) {
+            println!("WARNING: couldn't save print: {e}");
+        }
+    }
+
+    fn write_png(&self, page: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.out_dir)?;
+        let path = (1..)
+            .map(|i| self.out_dir.join(format!("print-{i:04}.png")))
+            .find(|path| !path.exists())
+            .unwrap();
+
+        let height = page.len() / SCREEN_WIDTH;
+        let file = BufWriter::new(File::create(&path)?);
+        let mut encoder = png::Encoder::new(file, SCREEN_WIDTH as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(page)?;
+        println!("printed {}", path.display());
+        Ok(())
+    }
+}
+
+// rle, a control byte with the top bit set repeats the next byte (ctrl & 0x7F) + 2 times,
+// otherwise the next ctrl + 1 bytes are copied as is
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let ctrl = data[i];
+        i += 1;
+        if ctrl & 0x80 != 0 {
+            let Some(&val) = data.get(i) else { break };
+            out.extend(std::iter::repeat_n(val, (ctrl & 0x7F) as usize + 2));
+            i += 1;
+        } else {
+            let end = (i + ctrl as usize + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    out
+}
+
+impl SerialDevice for Printer {
+    fn send(&mut self, out: u8) {
+        self.reply = self.receive(out);
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        Some(self.reply)
+    }
+
+    // the printer never drives the clock
+    fn poll(&mut self, _sb: u8, _ready: bool) -> Option<u8> {
+        None
+    }
+}
+
+impl Drop for Printer {
+    // whatever's still on the paper when the emulator closes
+    fn drop(&mut self) {
+        if !self.page.is_empty() {
+            self.save_page();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn packet(command: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![
+            0x88,
+            0x33,
+            command,
+            0x00,
+            data.len() as u8,
+            (data.len() >> 8) as u8,
+        ];
+        bytes.extend_from_slice(data);
+        let sum = bytes[2..]
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        bytes.extend_from_slice(&[sum as u8, (sum >> 8) as u8, 0x00, 0x00]);
+        bytes
+    }
+
+    // everything the printer shifts back for the packet, the last two are 0x81 and the status
+    fn send_packet(printer: &mut Printer, bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .map(|&b| {
+                printer.send(b);
+                printer.recv().unwrap()
+            })
+            .collect()
+    }
+
+    fn test_printer() -> Printer {
+        Printer::new(env::temp_dir().join(format!("gb-printer-{}", std::process::id())))
+    }
+
+    #[test]
+    fn decompresses() {
+        assert_eq!(decompress(&[0x02, 1, 2, 3]), [1, 2, 3]);
+        assert_eq!(decompress(&[0x81, 7]), [7, 7, 7]);
+        assert_eq!(decompress(&[0x00, 9, 0x80, 5, 0x01, 1, 2]), [9, 5, 5, 1, 2]);
+        // cut off partway, whatever's there is kept
+        assert_eq!(decompress(&[0x03, 1, 2]), [1, 2]);
+        assert_eq!(decompress(&[0x85]), [0u8; 0]);
+    }
+
+    #[test]
+    fn packets() {
+        let mut printer = test_printer();
+        // junk and a broken sync before the real packet get skipped
+        let mut bytes = vec![0x00, 0x12, 0x88, 0x00];
+        bytes.extend(packet(0x01, &[]));
+        let reply = send_packet(&mut printer, &bytes);
+        assert_eq!(reply[reply.len() - 2..], [0x81, 0x00]);
+
+        let reply = send_packet(&mut printer, &packet(0x04, &[0xAA; 0x280]));
+        assert_eq!(reply[reply.len() - 2..], [0x81, UNPROCESSED]);
+        assert!(reply[..reply.len() - 2].iter().all(|&b| b == 0));
+
+        let mut bad = packet(0x0F, &[]);
+        bad[6] ^= 1;
+        let reply = send_packet(&mut printer, &bad);
+        assert_eq!(reply[reply.len() - 1], UNPROCESSED | CHECKSUM_ERROR);
+        // a good packet clears it again
+        let reply = send_packet(&mut printer, &packet(0x0F, &[]));
+        assert_eq!(reply[reply.len() - 1], UNPROCESSED);
+
+        // no margin after, so the page stays in the printer and nothing gets saved
+        let reply = send_packet(&mut printer, &packet(0x02, &[1, 0x00, 0xE4, 0x40]));
+        assert_eq!(reply[reply.len() - 1], PRINTING);
+        assert_eq!(printer.page.len(), 16 * SCREEN_WIDTH);
+    }
+
+    #[test]
+    fn palette_0_is_the_default() {
+        let mut tiles = vec![];
+        for index in 0..4u8 {
+            let (lo, hi) = ((index & 1) * 0xFF, (index >> 1) * 0xFF);
+            tiles.extend([lo, hi].repeat(8 * 5));
+        }
+        let page = |palette| {
+            let mut printer = test_printer();
+            printer.buffer = tiles.clone();
+            printer.print(1, 0x00, palette);
+            printer.page.clone()
+        };
+        assert_eq!(page(0x00), page(0xE4));
+        assert_eq!(
+            page(0xE4)[..SCREEN_WIDTH]
+                .iter()
+                .step_by(40)
+                .collect::<Vec<_>>(),
+            [&255, &170, &85, &0]
+        );
+
+        let mut printer = test_printer();
+        printer.buffer = tiles;
+        printer.print(0, 0x00, 0xE4);
+        assert!(printer.page.is_empty());
+    }
+}
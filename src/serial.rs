@@ -1,16 +1,66 @@
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    io::{self, Write},
+    rc::Rc,
+};
+
 use proc_bitfield::bitfield;
 
-use crate::gameboy::CLOCK_SPEED;
+// the internal clock is a bit of the system counter (the one DIV is the top half of), a bit
+// gets shifted every time it goes from 1 to 0. that's 8192Hz normally and 262144Hz for the
+// CGB's fast mode
+const CLOCK_BIT: u32 = 8;
+const FAST_CLOCK_BIT: u32 = 3;
+
+// whatever's plugged into the link port. transfers are a byte at a time instead of bit by bit,
+// the two sides swap their SB contents and that's all either one can see anyway
+pub trait SerialDevice
+where
+    Self: Debug,
+{
+    // we're driving the clock, `out` is what's in SB as the transfer starts
+    fn send(&mut self, out: u8);
+    // the device's byte for the last send, None while it's still on its way
+    fn recv(&mut self) -> Option<u8>;
+    // the device driving the clock instead. `ready` is whether there's a transfer waiting on the
+    // external clock, only then does the byte that comes back count
+    fn poll(&mut self, sb: u8, ready: bool) -> Option<u8>;
+}
+
+// keeps everything the game sends as text, test roms like blargg's print their results this way
+#[derive(Debug, Default)]
+pub struct Capture {
+    pub output: Rc<RefCell<String>>, // shared so it can still be read once the gameboy has it
+    pub echo: bool,                  // print it to stdout as it comes in too
+}
+
+impl SerialDevice for Capture {
+    fn send(&mut self, out: u8) {
+        self.output.borrow_mut().push(out as char);
+        if self.echo {
+            print!("{}", out as char);
+            let _ = io::stdout().flush();
+        }
+    }
 
-const UPDATE_FREQ: u64 = CLOCK_SPEED / 8192;
+    // nothing on the other end
+    fn recv(&mut self) -> Option<u8> {
+        Some(0xFF)
+    }
+
+    fn poll(&mut self, _sb: u8, _ready: bool) -> Option<u8> {
+        None
+    }
+}
 
-// this is just a stub, maybe i'll fill it out eventually
 #[derive(Debug)]
 pub struct Serial {
     pub sb: u8,
     pub sc: Sc,
     pub serial_int: bool,
-    cycles: u64,
+    pub device: Option<Box<dyn SerialDevice>>, // nothing plugged in reads as all 1s
+    pub cgb: bool,                             // the fast clock bit only exists on a CGB
     bit_counter: u8,
 }
 
@@ -29,34 +79,65 @@ impl Serial {
             sb: 0,
             sc: 0.into(),
             serial_int: false,
-            cycles: 0,
+            device: None,
+            cgb: false,
             bit_counter: 0,
         }
     }
 
-    pub fn tick(&mut self, cycles: u64) {
+    // `counter` is the system counter from before these cycles
+    pub fn tick(&mut self, cycles: u64, counter: u16) {
         if !self.sc.clock_select() || !self.sc.transfer_enable() {
+            let ready = self.sc.transfer_enable();
+            if let Some(val) = self.device.as_mut().and_then(|d| d.poll(self.sb, ready))
+                && ready
+            {
+                self.finish(val);
+            }
             return;
         }
 
-        self.cycles += cycles;
-        if self.cycles >= UPDATE_FREQ {
-            self.cycles -= UPDATE_FREQ;
-            self.bit_counter += 1;
-            self.sb = (self.sb << 1) | 1;
-
+        let bit = match self.cgb && self.sc.clock_speed() {
+            true => FAST_CLOCK_BIT,
+            false => CLOCK_BIT,
+        };
+        // the bit falls every time the counter passes a multiple of 2^(bit + 1)
+        let start = counter as u64;
+        let edges = ((start + cycles) >> (bit + 1)) - (start >> (bit + 1));
+        for _ in 0..edges {
             if self.bit_counter >= 8 {
-                self.bit_counter = 0;
-                self.sc.set_transfer_enable(false);
-                self.serial_int = true;
+                break;
+            }
+            // what's coming in is only known once the device answers, until then it looks like
+            // nothing's connected
+            self.sb = (self.sb << 1) | 1;
+            self.bit_counter += 1;
+        }
+        // all the bits are out, but the other end might still be catching up
+        if self.bit_counter >= 8 {
+            match &mut self.device {
+                Some(device) => {
+                    if let Some(val) = device.recv() {
+                        self.finish(val);
+                    }
+                }
+                None => self.finish(0xFF),
             }
         }
     }
 
+    fn finish(&mut self, val: u8) {
+        self.sb = val;
+        self.bit_counter = 0;
+        self.sc.set_transfer_enable(false);
+        self.serial_int = true;
+    }
+
     pub fn read_u8(&self, addr: u16) -> u8 {
         match addr {
             0xFF01 => self.sb,
-            0xFF02 => self.sc.into(),
+            // unused bits read as 1, the CGB has one less of them
+            0xFF02 => u8::from(self.sc) | if self.cgb { 0x7C } else { 0x7E },
             _ => unimplemented!(),
         }
     }
@@ -64,8 +145,67 @@ impl Serial {
     pub fn write_u8(&mut self, addr: u16, val: u8) {
         match addr {
             0xFF01 => self.sb = val,
-            0xFF02 => self.sc = val.into(),
+            0xFF02 => {
+                self.sc = val.into();
+                // any write starts over, a transfer that was going gets dropped
+                self.bit_counter = 0;
+                if self.sc.transfer_enable()
+                    && self.sc.clock_select()
+                    && let Some(device) = &mut self.device
+                {
+                    device.send(self.sb);
+                }
+            }
             _ => unimplemented!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture() {
+        let capture = Capture::default();
+        let output = capture.output.clone();
+        let mut serial = Serial::new();
+        serial.device = Some(Box::new(capture));
+
+        let mut counter: u16 = 0;
+        for c in "Passed".bytes() {
+            serial.write_u8(0xFF01, c);
+            serial.write_u8(0xFF02, 0x81);
+            serial.serial_int = false;
+            while !serial.serial_int {
+                serial.tick(4, counter);
+                counter = counter.wrapping_add(4);
+            }
+            // nothing on the other end shifts in all 1s
+            assert_eq!(serial.read_u8(0xFF01), 0xFF);
+            assert!(!serial.sc.transfer_enable());
+        }
+        assert_eq!(*output.borrow(), "Passed");
+    }
+
+    // how many cycles a transfer with this SC takes
+    fn transfer_cycles(cgb: bool, sc: u8) -> u64 {
+        let mut serial = Serial::new();
+        serial.cgb = cgb;
+        serial.write_u8(0xFF02, sc);
+        let mut cycles = 0;
+        while !serial.serial_int {
+            serial.tick(4, cycles as u16);
+            cycles += 4;
+        }
+        cycles
+    }
+
+    #[test]
+    fn fast_clock() {
+        assert_eq!(transfer_cycles(false, 0x81), 4096);
+        // the speed bit does nothing on DMG
+        assert_eq!(transfer_cycles(false, 0x83), 4096);
+        assert_eq!(transfer_cycles(true, 0x83), 128);
+    }
+}
@@ -1,75 +1,217 @@
-const TAC_VALS: [u64; 4] = [1024, 16, 64, 256];
+use proc_bitfield::bitfield;
 
+// which bit of the system counter TIMA watches for each TAC clock select, 4096Hz, 262144Hz,
+// 65536Hz and 16384Hz
+const TAC_BITS: [u32; 4] = [9, 3, 5, 7];
+
+// everything hangs off one 16-bit counter that goes up every cycle, DIV is just its top half.
+// TIMA goes up when the selected bit AND the enable bit goes from 1 to 0, so anything that
+// knocks that signal low (resetting DIV, changing TAC) can bump TIMA too, same as on hardware.
+// based on https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
 #[derive(Debug)]
 pub struct Timer {
-    pub div: u8,
+    pub counter: u16,
     pub tima: u8,
     pub tma: u8,
-    pub tac: u8,
+    pub tac: Tac,
     pub timer_int: bool,
-    tima_state: u64,
-    div_state: u64,
+    overflowed: bool, // TIMA overflowed last M-cycle and reads 0 until the reload
+    reloading: bool,  // this M-cycle TIMA got reloaded from TMA
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Tac(pub u8): Debug, FromStorage, IntoStorage, DerefStorage {
+        pub enable: bool @ 2,
+        pub clock_select: u8 @ 0..=1,
+    }
 }
 
 impl Timer {
     pub fn new() -> Self {
         Self {
-            div: 0,
+            counter: 0,
             tima: 0,
             tma: 0,
-            tac: 0,
+            tac: 0.into(),
             timer_int: false,
-            tima_state: 0,
-            div_state: 0,
+            overflowed: false,
+            reloading: false,
         }
     }
 
+    // the input TIMA counts falling edges of
+    fn signal(&self) -> bool {
+        let bit = TAC_BITS[self.tac.clock_select() as usize];
+        self.tac.enable() && self.counter & (1 << bit) != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.overflowed = overflow;
+    }
+
+    // the bus only ever ticks whole M-cycles
     pub fn tick(&mut self, cycles: u64) {
-        // update div
-        self.div_state += cycles;
-        while self.div_state >= 256 {
-            self.div = self.div.wrapping_add(1);
-            self.div_state -= 256;
-        }
+        for _ in 0..cycles / 4 {
+            // TMA gets loaded a full M-cycle after the overflow, writes can still cancel it
+            // until then
+            self.reloading = self.overflowed;
+            if self.overflowed {
+                self.overflowed = false;
+                self.tima = self.tma;
+                self.timer_int = true;
+            }
 
-        // update tima
-        if self.tac & 0b100 != 0 {
-            let inc = TAC_VALS[(self.tac & 0b11) as usize];
-            self.tima_state += cycles;
-
-            while self.tima_state >= inc {
-                self.tima_state -= inc;
-                if self.tima == 0xFF {
-                    self.tima = self.tma;
-                    self.timer_int = true;
-                } else {
-                    self.tima = self.tima.wrapping_add(1);
-                }
+            let before = self.signal();
+            self.counter = self.counter.wrapping_add(4);
+            if before && !self.signal() {
+                self.increment();
             }
         }
     }
 
+    pub fn div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
     pub fn read_u8(&self, addr: u16) -> u8 {
         match addr {
-            0xFF04 => self.div,
+            0xFF04 => self.div(),
             0xFF05 => self.tima,
             0xFF06 => self.tma,
-            0xFF07 => self.tac,
+            0xFF07 => u8::from(self.tac) | 0xF8,
             _ => panic!("invalid read from timer at address {addr:#06x}"),
         }
     }
 
     pub fn write_u8(&mut self, addr: u16, val: u8) {
         match addr {
-            0xFF04 => self.div = 0,
-            0xFF05 => self.tima = val,
-            0xFF06 => self.tma = val,
-            0xFF07 => self.tac = val,
+            0xFF04 => self.reset_divider(),
+            0xFF05 => {
+                // the reload wins over a write in the same M-cycle, but a write before it
+                // cancels the reload and the interrupt
+                if !self.reloading {
+                    self.tima = val;
+                    self.overflowed = false;
+                }
+            }
+            0xFF06 => {
+                self.tma = val;
+                if self.reloading {
+                    self.tima = val;
+                }
+            }
+            0xFF07 => {
+                let before = self.signal();
+                self.tac = val.into();
+                if before && !self.signal() {
+                    self.increment();
+                }
+            }
             _ => panic!("invalid write to timer at address {addr:#06x}"),
         }
     }
 
+    // the counter this and the serial port run off of
+    pub fn system_counter(&self) -> u16 {
+        self.counter
+    }
+
     pub fn reset_divider(&mut self) {
-        self.div = 0;
+        let before = self.signal();
+        self.counter = 0;
+        if before {
+            self.increment();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::gameboy::test_gameboy;
+
+    // runs until TIMA overflows, leaving the bus right after the M-cycle it happened in
+    fn overflow(bus: &mut Bus) {
+        bus.write_u8(0xFF04, 0);
+        bus.write_u8(0xFF07, 0x05); // every 16 cycles
+        bus.write_u8(0xFF06, 0x42);
+        bus.write_u8(0xFF05, 0xFF);
+        while bus.timer.tima != 0 {
+            bus.tick(4);
+        }
+        bus.write_u8(0xFF0F, 0);
+    }
+
+    fn timer_int(bus: &Bus) -> bool {
+        bus.read_u8(0xFF0F) & 0x04 != 0
+    }
+
+    #[test]
+    fn overflow_reloads_a_cycle_later() {
+        let mut gb = test_gameboy(0x00, &[]);
+        let bus = &mut gb.cpu.bus;
+        overflow(bus);
+        assert!(!timer_int(bus));
+        bus.tick(4);
+        assert_eq!(bus.read_u8(0xFF05), 0x42);
+        assert!(timer_int(bus));
+    }
+
+    #[test]
+    fn tima_write_cancels_the_reload() {
+        let mut gb = test_gameboy(0x00, &[]);
+        let bus = &mut gb.cpu.bus;
+        overflow(bus);
+        bus.write_u8(0xFF05, 0x10);
+        bus.tick(4);
+        assert_eq!(bus.read_u8(0xFF05), 0x10);
+        assert!(!timer_int(bus));
+    }
+
+    #[test]
+    fn writes_during_the_reload() {
+        let mut gb = test_gameboy(0x00, &[]);
+        let bus = &mut gb.cpu.bus;
+        overflow(bus);
+        bus.tick(4);
+        // TIMA is ignored, TMA goes straight through
+        bus.write_u8(0xFF05, 0x10);
+        assert_eq!(bus.read_u8(0xFF05), 0x42);
+        bus.write_u8(0xFF06, 0x77);
+        assert_eq!(bus.read_u8(0xFF05), 0x77);
+        // and only for that one M-cycle
+        bus.tick(4);
+        bus.write_u8(0xFF06, 0x33);
+        assert_eq!(bus.read_u8(0xFF05), 0x77);
+    }
+
+    #[test]
+    fn falling_edge_glitches() {
+        let mut gb = test_gameboy(0x00, &[]);
+        let bus = &mut gb.cpu.bus;
+        bus.write_u8(0xFF04, 0);
+        bus.write_u8(0xFF07, 0x05);
+        bus.write_u8(0xFF05, 0);
+        // bit 3 of the counter is high, resetting DIV drops it
+        bus.tick(8);
+        bus.write_u8(0xFF04, 0);
+        assert_eq!(bus.read_u8(0xFF05), 1);
+        // bit 3 low, nothing happens
+        bus.write_u8(0xFF04, 0);
+        assert_eq!(bus.read_u8(0xFF05), 1);
+
+        // turning the timer off or switching to a bit that's low does the same
+        bus.tick(8);
+        bus.write_u8(0xFF07, 0x01);
+        assert_eq!(bus.read_u8(0xFF05), 2);
+        bus.write_u8(0xFF07, 0x05);
+        bus.write_u8(0xFF07, 0x04); // bit 9 is low
+        assert_eq!(bus.read_u8(0xFF05), 3);
+        bus.write_u8(0xFF07, 0x05);
+        bus.write_u8(0xFF07, 0x07); // bit 7 is low too
+        assert_eq!(bus.read_u8(0xFF05), 4);
     }
 }
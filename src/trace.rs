@@ -0,0 +1,77 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use crate::bus::Bus;
+use crate::cpu::RegisterFile;
+
+// writes one line per instruction in the same format as gameboy doctor, so logs can be diffed
+// against its reference traces: https://github.com/robert/gameboy-doctor
+pub struct Tracer {
+    out: BufWriter<File>,
+    pub pc_range: Option<RangeInclusive<u16>>,
+    pub bank: Option<usize>, // code outside the cart rom counts as bank 0
+    pub max_lines: Option<u64>,
+    lines: u64,
+}
+
+impl Tracer {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            pc_range: None,
+            bank: None,
+            max_lines: None,
+            lines: 0,
+        })
+    }
+
+    // called right before the instruction at PC runs
+    pub fn log(&mut self, rf: &RegisterFile, bus: &Bus) {
+        if self.max_lines.is_some_and(|max| self.lines >= max) {
+            return;
+        }
+        if let Some(range) = &self.pc_range
+            && !range.contains(&rf.pc)
+        {
+            return;
+        }
+        if let Some(bank) = self.bank
+            && bus.rom_bank(rf.pc) != bank
+        {
+            return;
+        }
+
+        let pcmem = |i| bus.peek(rf.pc.wrapping_add(i));
+        let line = format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            rf.a,
+            rf.f,
+            rf.b,
+            rf.c,
+            rf.d,
+            rf.e,
+            rf.h,
+            rf.l,
+            rf.sp,
+            rf.pc,
+            pcmem(0),
+            pcmem(1),
+            pcmem(2),
+            pcmem(3),
+        );
+        if let Err(e) = writeln!(self.out, "{line}") {
+            println!("WARNING: couldn't write trace, stopping it: {e}");
+            self.max_lines = Some(0);
+            return;
+        }
+        self.lines += 1;
+        if self.max_lines.is_some_and(|max| self.lines >= max) {
+            println!("trace hit its limit of {} lines", self.lines);
+            let _ = self.out.flush();
+        }
+    }
+}